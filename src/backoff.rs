@@ -0,0 +1,127 @@
+use std::fmt;
+use std::error::Error;
+use std::time::Duration;
+
+use rng::*;
+
+// Exponentially growing delay between runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    // delay of each next run is this many times longer than the last one
+    pub multiplier: f64,
+    pub max: Duration,
+    // fraction of the delay that is randomized; delay is picked from [delay * (1 - jitter), delay]
+    pub jitter: f64
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackoffError {
+    MaxLessThanInitial,
+    InvalidMultiplier(f64),
+    InvalidJitter(f64)
+}
+
+impl fmt::Display for BackoffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackoffError::MaxLessThanInitial => write!(f, "max delay must not be less than initial delay"),
+            BackoffError::InvalidMultiplier(multiplier) => write!(f, "multiplier must be at least 1 but got {}", multiplier),
+            BackoffError::InvalidJitter(jitter) => write!(f, "jitter must be between 0 and 1 but got {}", jitter)
+        }
+    }
+}
+
+impl Error for BackoffError {
+    fn description(&self) -> &str {
+        "invalid backoff parameters"
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, multiplier: f64, max: Duration) -> Result<Backoff, BackoffError> {
+        Backoff::with_jitter(initial, multiplier, max, 0.0)
+    }
+
+    pub fn with_jitter(initial: Duration, multiplier: f64, max: Duration, jitter: f64) -> Result<Backoff, BackoffError> {
+        let backoff = Backoff {
+            initial,
+            multiplier,
+            max,
+            jitter
+        };
+        backoff.check()?;
+        Ok(backoff)
+    }
+
+    // fields are public so backoff built directly is checked before it is scheduled
+    pub fn check(&self) -> Result<(), BackoffError> {
+        if self.max < self.initial {
+            return Err(BackoffError::MaxLessThanInitial);
+        }
+        if self.multiplier.is_nan() || self.multiplier < 1.0 {
+            return Err(BackoffError::InvalidMultiplier(self.multiplier));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(BackoffError::InvalidJitter(self.jitter));
+        }
+        Ok(())
+    }
+
+    // delay following given one without jitter
+    pub fn next_delay(&self, delay: Duration) -> Duration {
+        let delay = delay.as_nanos() as f64 * self.multiplier;
+        if delay >= self.max.as_nanos() as f64 {
+            self.max
+        } else {
+            Duration::from_nanos(delay as u64)
+        }
+    }
+
+    pub fn jittered(&self, delay: Duration, rng: &mut JitterRng) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let nanoseconds = delay.as_nanos() as f64;
+        delay.checked_sub(Duration::from_nanos((nanoseconds * self.jitter * rng.next_f64()) as u64)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn next_delay() {
+        let backoff = Backoff::new(Duration::from_secs(1), 2.0, Duration::from_secs(5)).unwrap();
+        assert_eq!(backoff.next_delay(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(backoff.next_delay(Duration::from_secs(2)), Duration::from_secs(4));
+        assert_eq!(backoff.next_delay(Duration::from_secs(4)), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(Duration::from_secs(5)), Duration::from_secs(5));
+
+        let backoff = Backoff::new(Duration::from_millis(100), 1.5, Duration::from_secs(5)).unwrap();
+        assert_eq!(backoff.next_delay(Duration::from_millis(100)), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn jittered() {
+        let mut rng = JitterRng::new(42);
+        let backoff = Backoff::new(Duration::from_secs(1), 2.0, Duration::from_secs(5)).unwrap();
+        assert_eq!(backoff.jittered(Duration::from_secs(2), &mut rng), Duration::from_secs(2));
+
+        let backoff = Backoff::with_jitter(Duration::from_secs(1), 2.0, Duration::from_secs(5), 0.5).unwrap();
+        for _ in 0..100 {
+            let delay = backoff.jittered(Duration::from_secs(2), &mut rng);
+            assert!(delay > Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(Backoff::new(Duration::from_secs(2), 2.0, Duration::from_secs(1)), Err(BackoffError::MaxLessThanInitial));
+        assert_eq!(Backoff::new(Duration::from_secs(1), 0.5, Duration::from_secs(5)), Err(BackoffError::InvalidMultiplier(0.5)));
+        assert!(Backoff::new(Duration::from_secs(1), f64::NAN, Duration::from_secs(5)).is_err());
+        assert_eq!(Backoff::with_jitter(Duration::from_secs(1), 2.0, Duration::from_secs(5), 1.5), Err(BackoffError::InvalidJitter(1.5)));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+
+use task::*;
+use task_queue::*;
+
+pub struct BTreeTaskQueue<Token> where Token: Clone {
+    tasks: BTreeMap<PointInTime, Vec<Task<Token>>>,
+    task_time_points: HashMap<TaskId, PointInTime>
+}
+
+impl<Token> Default for BTreeTaskQueue<Token> where Token: Clone {
+    fn default() -> BTreeTaskQueue<Token> {
+        BTreeTaskQueue::new()
+    }
+}
+
+impl<Token> BTreeTaskQueue<Token> where Token: Clone {
+    pub fn new() -> BTreeTaskQueue<Token> {
+        BTreeTaskQueue {
+            tasks: BTreeMap::new(),
+            task_time_points: HashMap::new()
+        }
+    }
+}
+
+impl<Token> TaskQueue<Token> for BTreeTaskQueue<Token> where Token: Clone {
+    fn insert(&mut self, time_point: PointInTime, task: Task<Token>) {
+        self.task_time_points.insert(task.id, time_point);
+        self.tasks.entry(time_point).or_default().push(task);
+    }
+
+    fn remove(&mut self, id: TaskId) -> Option<Task<Token>> {
+        let time_point = self.task_time_points.remove(&id)?;
+        let (task, empty) = {
+            let tasks = self.tasks.get_mut(&time_point).expect("indexed time point not found");
+            let position = tasks.iter().position(|task| task.id == id).expect("indexed task not found");
+            (tasks.remove(position), tasks.is_empty())
+        };
+        if empty {
+            self.tasks.remove(&time_point);
+        }
+        Some(task)
+    }
+
+    fn get(&self, id: TaskId) -> Option<&Task<Token>> {
+        self.task_time_points.get(&id)
+            .and_then(|time_point| self.tasks.get(time_point))
+            .and_then(|tasks| tasks.iter().find(|task| task.id == id))
+    }
+
+    fn get_mut(&mut self, id: TaskId) -> Option<&mut Task<Token>> {
+        let time_point = self.task_time_points.get(&id)?;
+        self.tasks.get_mut(time_point)
+            .and_then(|tasks| tasks.iter_mut().find(|task| task.id == id))
+    }
+
+    fn first_time_point(&self) -> Option<PointInTime> {
+        self.tasks.keys().next().cloned()
+    }
+
+    fn time_points_before(&self, time_point: PointInTime) -> Vec<PointInTime> {
+        self.tasks.range(..time_point).map(|(&time_point, _)| time_point).collect()
+    }
+
+    fn take(&mut self, time_point: PointInTime) -> Vec<Task<Token>> {
+        let tasks = self.tasks.remove(&time_point).unwrap_or_default();
+        for task in tasks.iter() {
+            self.task_time_points.remove(&task.id);
+        }
+        tasks
+    }
+
+    fn retain<F>(&mut self, mut f: F) where F: FnMut(&Task<Token>) -> bool {
+        let mut empty_time_points = vec![];
+
+        for (time_point, tasks) in self.tasks.iter_mut() {
+            let task_time_points = &mut self.task_time_points;
+            tasks.retain(|task| {
+                let keep = f(task);
+                if !keep {
+                    task_time_points.remove(&task.id);
+                }
+                keep
+            });
+            if tasks.is_empty() {
+                empty_time_points.push(*time_point);
+            }
+        }
+
+        for time_point in empty_time_points {
+            self.tasks.remove(&time_point).unwrap();
+        }
+    }
+
+    fn tasks(&self) -> Vec<&Task<Token>> {
+        self.tasks.values().flat_map(|tasks| tasks.iter()).collect()
+    }
+
+    fn drain(&mut self) -> Vec<Task<Token>> {
+        self.task_time_points.clear();
+        mem::take(&mut self.tasks).into_values().flatten().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.task_time_points.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn task(id: u64) -> Task<u64> {
+        Task::new(TaskId(id), Duration::new(0, 0), Duration::new(0, 0), TaskBond::OneOff, id).unwrap()
+    }
+
+    fn ids(tasks: Vec<Task<u64>>) -> Vec<u64> {
+        tasks.into_iter().map(|task| task.token).collect()
+    }
+
+    #[test]
+    fn insert_take() {
+        let mut queue = BTreeTaskQueue::new();
+        assert_eq!(queue.first_time_point(), None);
+
+        queue.insert(3, task(0));
+        queue.insert(1, task(1));
+        queue.insert(3, task(2));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.first_time_point(), Some(1));
+        assert_eq!(queue.time_points_before(3), vec![1]);
+        assert_eq!(queue.time_points_before(4), vec![1, 3]);
+
+        assert_eq!(ids(queue.take(1)), vec![1]);
+        assert_eq!(ids(queue.take(3)), vec![0, 2]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn remove_retain_drain() {
+        let mut queue = BTreeTaskQueue::new();
+
+        queue.insert(3, task(0));
+        queue.insert(1, task(1));
+        queue.insert(3, task(2));
+        queue.insert(5, task(3));
+
+        assert_eq!(queue.remove(TaskId(1)).map(|task| task.token), Some(1));
+        assert_eq!(queue.remove(TaskId(1)).map(|task| task.token), None);
+        assert_eq!(queue.first_time_point(), Some(3));
+
+        queue.retain(|task| task.token != 2);
+        assert!(queue.get(TaskId(2)).is_none());
+        assert_eq!(queue.get(TaskId(0)).map(|task| task.token), Some(0));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.tasks().iter().map(|task| task.token).collect::<Vec<_>>(), vec![0, 3]);
+
+        assert_eq!(ids(queue.drain()), vec![0, 3]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.first_time_point(), None);
+    }
+}
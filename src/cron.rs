@@ -0,0 +1,357 @@
+use std::fmt;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+// Feb 29th falling on given day of week may take this long to come around
+const SEARCH_YEARS: i64 = 28;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CronField {
+    Minute,
+    Hour,
+    DayOfMonth,
+    Month,
+    DayOfWeek
+}
+
+impl CronField {
+    fn range(&self) -> (u32, u32) {
+        match *self {
+            CronField::Minute => (0, 59),
+            CronField::Hour => (0, 23),
+            CronField::DayOfMonth => (1, 31),
+            CronField::Month => (1, 12),
+            // both 0 and 7 are Sunday
+            CronField::DayOfWeek => (0, 7)
+        }
+    }
+
+    fn names(&self) -> &'static [&'static str] {
+        match *self {
+            CronField::Month => &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"],
+            CronField::DayOfWeek => &["sun", "mon", "tue", "wed", "thu", "fri", "sat"],
+            _ => &[]
+        }
+    }
+}
+
+impl fmt::Display for CronField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CronField::Minute => write!(f, "minute"),
+            CronField::Hour => write!(f, "hour"),
+            CronField::DayOfMonth => write!(f, "day of month"),
+            CronField::Month => write!(f, "month"),
+            CronField::DayOfWeek => write!(f, "day of week")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CronError {
+    FieldCount(usize),
+    InvalidField(CronField, String),
+    OutOfRange(CronField, u32),
+    NeverFires,
+    // next run is too far in the future for the scheduler
+    Overflow
+}
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CronError::FieldCount(count) => write!(f, "cron expression needs 5 fields but got {}", count),
+            CronError::InvalidField(field, ref value) => write!(f, "invalid {} field: {:?}", field, value),
+            CronError::OutOfRange(field, value) => write!(f, "{} value {} is out of range", field, value),
+            CronError::NeverFires => write!(f, "cron expression never matches any time"),
+            CronError::Overflow => write!(f, "next run of cron expression is too far in the future")
+        }
+    }
+}
+
+impl Error for CronError {
+    fn description(&self) -> &str {
+        "problem while parsing cron expression"
+    }
+}
+
+// Standard 5 field cron expression matched in UTC
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    // when both day fields are restricted either of them has to match (like in Vixie cron)
+    days_of_month_any: bool,
+    days_of_week_any: bool
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(expression: &str) -> Result<CronSchedule, CronError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(CronError::FieldCount(fields.len()));
+        }
+
+        let mut days_of_week = parse_field(fields[4], CronField::DayOfWeek)?;
+        if days_of_week & 1 << 7 != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], CronField::Minute)?,
+            hours: parse_field(fields[1], CronField::Hour)?,
+            days_of_month: parse_field(fields[2], CronField::DayOfMonth)?,
+            months: parse_field(fields[3], CronField::Month)?,
+            days_of_week,
+            days_of_month_any: fields[2].starts_with('*'),
+            days_of_week_any: fields[4].starts_with('*')
+        })
+    }
+}
+
+fn parse_value(value: &str, field: CronField) -> Result<u32, CronError> {
+    let lower = value.to_lowercase();
+    if let Some(position) = field.names().iter().position(|name| *name == lower) {
+        let first = if field == CronField::Month { 1 } else { 0 };
+        return Ok(first + position as u32);
+    }
+
+    let value: u32 = value.parse().map_err(|_| CronError::InvalidField(field, value.to_owned()))?;
+    let (min, max) = field.range();
+    if value < min || value > max {
+        return Err(CronError::OutOfRange(field, value));
+    }
+    Ok(value)
+}
+
+fn parse_field(expression: &str, field: CronField) -> Result<u64, CronError> {
+    let invalid = || CronError::InvalidField(field, expression.to_owned());
+    let mut bits = 0;
+
+    for part in expression.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(index) => (&part[..index], Some(&part[index + 1..])),
+            None => (part, None)
+        };
+
+        let step = match step {
+            Some(step) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => step,
+                _ => return Err(invalid())
+            },
+            None => 1
+        };
+
+        let (min, max) = field.range();
+        let (first, last) = if range == "*" {
+            (min, max)
+        } else if let Some(index) = range.find('-') {
+            let first = parse_value(&range[..index], field)?;
+            let last = parse_value(&range[index + 1..], field)?;
+            if first > last {
+                return Err(invalid());
+            }
+            (first, last)
+        } else {
+            let first = parse_value(range, field)?;
+            // a/n means from a to the end of range every n
+            (first, if step > 1 { max } else { first })
+        };
+
+        let mut value = first;
+        while value <= last {
+            bits |= 1 << value;
+            value = match value.checked_add(step) {
+                Some(value) => value,
+                None => break
+            };
+        }
+    }
+
+    Ok(bits)
+}
+
+impl CronSchedule {
+    // First matching whole minute strictly after given time
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let mut minute = unix_seconds(time).div_euclid(60) + 1;
+        let (start_year, _, _) = civil_from_days(minute.div_euclid(MINUTES_PER_DAY));
+
+        loop {
+            let days = minute.div_euclid(MINUTES_PER_DAY);
+            let (year, month, day) = civil_from_days(days);
+
+            if year > start_year + SEARCH_YEARS {
+                return None;
+            }
+
+            if self.months & 1 << month == 0 {
+                minute = if month == 12 {
+                    days_from_civil(year + 1, 1, 1)
+                } else {
+                    days_from_civil(year, month + 1, 1)
+                } * MINUTES_PER_DAY;
+                continue;
+            }
+
+            if !self.matches_day(day, weekday_from_days(days)) {
+                minute = (days + 1) * MINUTES_PER_DAY;
+                continue;
+            }
+
+            let minute_of_day = minute.rem_euclid(MINUTES_PER_DAY);
+            if self.hours & 1 << (minute_of_day / 60) == 0 {
+                minute = days * MINUTES_PER_DAY + (minute_of_day / 60 + 1) * 60;
+                continue;
+            }
+
+            if self.minutes & 1 << (minute_of_day % 60) == 0 {
+                minute += 1;
+                continue;
+            }
+
+            return Some(from_unix_seconds(minute * 60));
+        }
+    }
+
+    fn matches_day(&self, day: u32, weekday: u32) -> bool {
+        let day_of_month = self.days_of_month & 1 << day != 0;
+        let day_of_week = self.days_of_week & 1 << weekday != 0;
+
+        if self.days_of_month_any || self.days_of_week_any {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        }
+    }
+}
+
+// whole seconds since Unix epoch rounded down
+fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(err) => {
+            let before = err.duration();
+            -(before.as_secs() as i64) - if before.subsec_nanos() > 0 { 1 } else { 0 }
+        }
+    }
+}
+
+fn from_unix_seconds(seconds: i64) -> SystemTime {
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+}
+
+// Howard Hinnant's days from/to civil algorithms for proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn weekday_from_days(days: i64) -> u32 {
+    // 1970-01-01 was Thursday
+    (days + 4).rem_euclid(7) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(year: i64, month: u32, day: u32, hour: i64, minute: i64) -> SystemTime {
+        from_unix_seconds(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60)
+    }
+
+    #[test]
+    fn civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 29)), (2000, 2, 29));
+        assert_eq!(civil_from_days(days_from_civil(1969, 12, 31)), (1969, 12, 31));
+        assert_eq!(weekday_from_days(days_from_civil(2024, 6, 2)), 0);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("* * * *".parse::<CronSchedule>(), Err(CronError::FieldCount(4)));
+        assert_eq!("60 * * * *".parse::<CronSchedule>(), Err(CronError::OutOfRange(CronField::Minute, 60)));
+        assert_eq!("* * 0 * *".parse::<CronSchedule>(), Err(CronError::OutOfRange(CronField::DayOfMonth, 0)));
+        assert_eq!("*/0 * * * *".parse::<CronSchedule>(), Err(CronError::InvalidField(CronField::Minute, "*/0".to_owned())));
+        assert_eq!("* x * * *".parse::<CronSchedule>(), Err(CronError::InvalidField(CronField::Hour, "x".to_owned())));
+        assert_eq!("* 5-2 * * *".parse::<CronSchedule>(), Err(CronError::InvalidField(CronField::Hour, "5-2".to_owned())));
+    }
+
+    #[test]
+    fn large_step() {
+        assert_eq!("1-5/4294967295 * * * *".parse::<CronSchedule>(), "1 * * * *".parse::<CronSchedule>());
+        assert_eq!("*/4294967295 * * * *".parse::<CronSchedule>(), "0 * * * *".parse::<CronSchedule>());
+    }
+
+    #[test]
+    fn every_five_minutes() {
+        let schedule: CronSchedule = "*/5 * * * *".parse().unwrap();
+        assert_eq!(schedule.next_after(at(2020, 1, 1, 0, 0)), Some(at(2020, 1, 1, 0, 5)));
+        assert_eq!(schedule.next_after(at(2020, 1, 1, 0, 3)), Some(at(2020, 1, 1, 0, 5)));
+        assert_eq!(schedule.next_after(at(2020, 12, 31, 23, 58)), Some(at(2021, 1, 1, 0, 0)));
+        assert_eq!(schedule.next_after(at(1969, 12, 31, 23, 58) + Duration::from_millis(500)), Some(at(1970, 1, 1, 0, 0)));
+    }
+
+    #[test]
+    fn lists_ranges_and_names() {
+        let schedule: CronSchedule = "30 9-17/4 * jan,mar mon-fri".parse().unwrap();
+        // 2021-01-01 was Friday
+        assert_eq!(schedule.next_after(at(2021, 1, 1, 9, 30)), Some(at(2021, 1, 1, 13, 30)));
+        assert_eq!(schedule.next_after(at(2021, 1, 1, 17, 30)), Some(at(2021, 1, 4, 9, 30)));
+        assert_eq!(schedule.next_after(at(2021, 1, 29, 17, 30)), Some(at(2021, 3, 1, 9, 30)));
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // 1st of the month or any Sunday
+        let schedule: CronSchedule = "0 0 1 * 7".parse().unwrap();
+        assert_eq!(schedule.next_after(at(2024, 6, 1, 0, 0)), Some(at(2024, 6, 2, 0, 0)));
+        assert_eq!(schedule.next_after(at(2024, 6, 30, 0, 0)), Some(at(2024, 7, 1, 0, 0)));
+    }
+
+    #[test]
+    fn leap_day() {
+        let schedule: CronSchedule = "0 12 29 2 *".parse().unwrap();
+        assert_eq!(schedule.next_after(at(2021, 1, 1, 0, 0)), Some(at(2024, 2, 29, 12, 0)));
+    }
+
+    #[test]
+    fn never_fires() {
+        let schedule: CronSchedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(schedule.next_after(at(2021, 1, 1, 0, 0)), None);
+    }
+}
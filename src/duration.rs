@@ -0,0 +1,52 @@
+use std::time::Duration;
+#[cfg(feature = "legacy-time")]
+use time;
+
+use scheduler::SchedulerError;
+
+// std::time::Duration or, with legacy-time feature, time::Duration
+pub trait IntoDuration {
+    fn into_duration(self) -> Result<Duration, SchedulerError>;
+}
+
+impl IntoDuration for Duration {
+    fn into_duration(self) -> Result<Duration, SchedulerError> {
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "legacy-time")]
+impl IntoDuration for time::Duration {
+    // std::time::Duration cannot be negative
+    fn into_duration(self) -> Result<Duration, SchedulerError> {
+        self.to_std().map_err(|_| SchedulerError::NegativeDuration((-self).to_std().unwrap_or_default()))
+    }
+}
+
+// negative timeout is taken for zero like a timeout that has already passed
+pub(crate) fn timeout_duration<D>(timeout: D) -> Duration where D: IntoDuration {
+    match timeout.into_duration() {
+        Ok(timeout) => timeout,
+        Err(SchedulerError::NegativeDuration(_)) => Duration::new(0, 0),
+        Err(err) => panic!("{}", err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn into_duration() {
+        assert_eq!(Duration::from_millis(1500).into_duration(), Ok(Duration::from_millis(1500)));
+    }
+
+    #[cfg(feature = "legacy-time")]
+    #[test]
+    fn into_duration_legacy() {
+        assert_eq!(time::Duration::milliseconds(1500).into_duration(), Ok(Duration::from_millis(1500)));
+        assert_eq!(time::Duration::seconds(-1).into_duration(), Err(SchedulerError::NegativeDuration(Duration::from_secs(1))));
+        assert_eq!(timeout_duration(time::Duration::seconds(-1)), Duration::new(0, 0));
+    }
+}
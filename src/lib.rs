@@ -1,14 +1,45 @@
-extern crate time;
+#[cfg(feature = "legacy-time")]
+pub extern crate time;
+#[cfg(feature = "stream")]
+extern crate futures_core;
+#[cfg(feature = "tokio-timer")]
+extern crate tokio;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "stream"))]
+extern crate futures_executor;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+mod duration;
+mod rng;
+mod backoff;
 mod task;
+mod cron;
 mod time_source;
+mod task_queue;
+mod btree_task_queue;
+mod timing_wheel_task_queue;
 mod scheduler;
 mod steady_time_source;
+mod system_time_source;
+#[cfg(any(test, feature = "testing"))]
+mod mock_time_source;
 #[cfg(test)]
 mod test_helpers;
 
-pub use time::Duration;
+pub use duration::*;
+pub use rng::*;
+pub use backoff::*;
+pub use task::*;
+pub use cron::*;
 pub use time_source::*;
+pub use task_queue::*;
+pub use btree_task_queue::*;
+pub use timing_wheel_task_queue::*;
 pub use steady_time_source::*;
+pub use system_time_source::*;
+#[cfg(any(test, feature = "testing"))]
+pub use mock_time_source::*;
 pub use scheduler::*;
 
@@ -0,0 +1,344 @@
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use time_source::*;
+
+struct MockClockState {
+    now: Duration,
+    epoch: SystemTime,
+    origin: Instant,
+    clock_jump: Option<ClockJump>,
+    aborted: bool
+}
+
+// Clones share the clock so it can be advanced from a test thread
+#[derive(Clone)]
+pub struct MockClock {
+    inner: Arc<(Mutex<MockClockState>, Condvar)>
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl MockClock {
+    // now() of zero is 1970-01-01 00:00:00 UTC (Thursday) unless clock was jumped
+    pub fn new() -> MockClock {
+        MockClock {
+            inner: Arc::new((Mutex::new(MockClockState {
+                now: Duration::new(0, 0),
+                epoch: UNIX_EPOCH,
+                origin: Instant::now(),
+                clock_jump: None,
+                aborted: false
+            }), Condvar::new()))
+        }
+    }
+
+    pub fn now(&self) -> Duration {
+        self.inner.0.lock().unwrap().now
+    }
+
+    pub fn epoch(&self) -> SystemTime {
+        self.inner.0.lock().unwrap().epoch
+    }
+
+    // Instant at which now() was zero; taken when the clock was created and does not follow advance()
+    pub fn origin(&self) -> Instant {
+        self.inner.0.lock().unwrap().origin
+    }
+
+    // move clock forward waking up any time source waiting on it
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.inner.0.lock().unwrap();
+        state.now += duration;
+        self.inner.1.notify_all();
+    }
+
+    // simulate wall clock being stepped; it will be reported on next clock_jump() call
+    pub fn jump_clock(&self, clock_jump: ClockJump) {
+        let offset = match clock_jump {
+            ClockJump::Reanchor(offset) | ClockJump::Report(offset) => offset
+        };
+        let mut state = self.inner.0.lock().unwrap();
+        state.epoch = offset.apply(state.epoch);
+        state.clock_jump = Some(clock_jump);
+    }
+
+    fn take_clock_jump(&self) -> Option<ClockJump> {
+        self.inner.0.lock().unwrap().clock_jump.take()
+    }
+
+    // takes pending abort so it ends one wait only
+    fn take_aborted(&self) -> bool {
+        let mut state = self.inner.0.lock().unwrap();
+        let aborted = state.aborted;
+        state.aborted = false;
+        aborted
+    }
+
+    fn abort(&self) {
+        self.inner.0.lock().unwrap().aborted = true;
+        self.inner.1.notify_all();
+    }
+
+    fn reset_abort(&self) {
+        self.inner.0.lock().unwrap().aborted = false;
+    }
+
+    // block until clock reaches given time or wait is aborted
+    fn wait_until(&self, time: Duration, abortable: bool) -> Result<(), WaitAbortedError> {
+        let (lock, condvar) = &*self.inner;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if abortable && state.aborted {
+                state.aborted = false;
+                return Err(WaitAbortedError);
+            }
+            if state.now >= time {
+                return Ok(());
+            }
+            state = condvar.wait(state).unwrap();
+        }
+    }
+}
+
+// Only moves when fast forwarded or advanced with its clock
+pub struct MockTimeSource {
+    clock: MockClock
+}
+
+impl Default for MockTimeSource {
+    fn default() -> MockTimeSource {
+        MockTimeSource::new()
+    }
+}
+
+impl MockTimeSource {
+    pub fn new() -> MockTimeSource {
+        MockTimeSource::with_clock(MockClock::new())
+    }
+
+    pub fn with_clock(clock: MockClock) -> MockTimeSource {
+        MockTimeSource {
+            clock
+        }
+    }
+
+    pub fn clock(&self) -> MockClock {
+        self.clock.clone()
+    }
+
+    pub fn jump_clock(&mut self, clock_jump: ClockJump) {
+        self.clock.jump_clock(clock_jump)
+    }
+}
+
+impl FastForward for MockTimeSource {
+    fn fast_forward(&mut self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+}
+
+impl TimeSource for MockTimeSource {
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn clock_jump(&mut self) -> Option<ClockJump> {
+        self.clock.take_clock_jump()
+    }
+}
+
+impl WallClock for MockTimeSource {
+    fn epoch(&self) -> SystemTime {
+        self.clock.epoch()
+    }
+}
+
+impl InstantClock for MockTimeSource {
+    fn time_at(&self, instant: Instant) -> Option<Duration> {
+        instant.checked_duration_since(self.clock.origin())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MockWaitMode {
+    // wait() moves the clock forward by the waited duration right away
+    AutoAdvance,
+    // wait() blocks until the clock is advanced with its handle from another thread
+    Manual
+}
+
+// Mock time source that can be waited on; copies share the clock
+#[derive(Clone)]
+pub struct MockTimeSourceWait {
+    clock: MockClock,
+    mode: MockWaitMode
+}
+
+impl Default for MockTimeSourceWait {
+    fn default() -> MockTimeSourceWait {
+        MockTimeSourceWait::new()
+    }
+}
+
+impl MockTimeSourceWait {
+    pub fn new() -> MockTimeSourceWait {
+        MockTimeSourceWait::with_clock(MockClock::new(), MockWaitMode::AutoAdvance)
+    }
+
+    pub fn with_clock(clock: MockClock, mode: MockWaitMode) -> MockTimeSourceWait {
+        MockTimeSourceWait {
+            clock,
+            mode
+        }
+    }
+
+    pub fn clock(&self) -> MockClock {
+        self.clock.clone()
+    }
+
+    pub fn jump_clock(&mut self, clock_jump: ClockJump) {
+        self.clock.jump_clock(clock_jump)
+    }
+
+    fn wait_for(&mut self, duration: Duration, abortable: bool) -> Result<(), WaitAbortedError> {
+        if abortable && self.clock.take_aborted() {
+            return Err(WaitAbortedError);
+        }
+
+        let until = self.clock.now() + duration;
+        if let MockWaitMode::AutoAdvance = self.mode {
+            self.clock.advance(duration);
+        }
+        self.clock.wait_until(until, abortable)
+    }
+}
+
+impl FastForward for MockTimeSourceWait {
+    fn fast_forward(&mut self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+}
+
+impl Wait for MockTimeSourceWait {
+    fn wait(&mut self, duration: Duration) {
+        self.wait_for(duration, false).unwrap()
+    }
+}
+
+impl TimeSource for MockTimeSourceWait {
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn clock_jump(&mut self) -> Option<ClockJump> {
+        self.clock.take_clock_jump()
+    }
+}
+
+impl WallClock for MockTimeSourceWait {
+    fn epoch(&self) -> SystemTime {
+        self.clock.epoch()
+    }
+}
+
+impl InstantClock for MockTimeSourceWait {
+    fn time_at(&self, instant: Instant) -> Option<Duration> {
+        instant.checked_duration_since(self.clock.origin())
+    }
+}
+
+#[derive(Clone)]
+pub struct MockTimeSourceAbortHandle {
+    clock: MockClock
+}
+
+impl Abort for MockTimeSourceAbortHandle {
+    fn abort(&self) {
+        self.clock.abort();
+    }
+
+    fn reset(&self) {
+        self.clock.reset_abort();
+    }
+}
+
+impl AbortableWait for MockTimeSourceWait {
+    type AbortHandle = MockTimeSourceAbortHandle;
+
+    fn abort_handle(&self) -> Self::AbortHandle {
+        MockTimeSourceAbortHandle {
+            clock: self.clock.clone()
+        }
+    }
+
+    fn abortable_wait(&mut self, duration: Duration) -> Result<(), WaitAbortedError> {
+        self.wait_for(duration, true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use scheduler::*;
+    use test_helpers::*;
+    use std::time::Duration;
+    use std::thread::{spawn, sleep};
+
+    #[test]
+    fn shared_clock() {
+        let time_source = MockTimeSource::new();
+        let clock = time_source.clock();
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(time_source.now(), Duration::from_secs(2));
+
+        clock.jump_clock(ClockJump::Report(ClockOffset::Forward(Duration::from_secs(60))));
+        assert_eq!(time_source.epoch(), UNIX_EPOCH + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn auto_advance() {
+        let time_source = MockTimeSourceWait::new();
+        let clock = time_source.clock();
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), time_source);
+        scheduler.after(Duration::from_secs(60 * 60), 1);
+
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![1]));
+        assert_eq!(clock.now(), Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn manual_advance_from_other_thread() {
+        let clock = MockClock::new();
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::with_clock(clock.clone(), MockWaitMode::Manual));
+        scheduler.after(Duration::from_secs(3), 1);
+
+        let handle = spawn(move || scheduler.wait().tokens());
+
+        clock.advance(Duration::from_secs(2));
+        sleep(Duration::from_millis(20));
+        assert!(!handle.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(handle.join().unwrap(), Ok(vec![1]));
+    }
+
+    #[test]
+    fn manual_abort() {
+        let clock = MockClock::new();
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::with_clock(clock.clone(), MockWaitMode::Manual));
+        scheduler.after(Duration::from_secs(3), 1);
+        let abort_handle = scheduler.abort_handle();
+
+        let handle = spawn(move || scheduler.abortable_wait().tokens());
+
+        sleep(Duration::from_millis(20));
+        abort_handle.abort();
+        assert_eq!(handle.join().unwrap(), Err(AbortableWaitError::Aborted));
+    }
+}
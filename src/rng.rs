@@ -0,0 +1,71 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+// SplitMix64 for task delay jitter; not for security
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JitterRng {
+    state: u64
+}
+
+impl JitterRng {
+    pub fn new(seed: u64) -> JitterRng {
+        JitterRng {
+            state: seed
+        }
+    }
+
+    // seeded from current time so that processes started together do not pick same values
+    pub fn from_time() -> JitterRng {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        JitterRng::new((now.as_nanos() as u64) ^ (u64::from(::std::process::id()) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniformly distributed in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniformly distributed in [0, max)
+    pub fn duration(&mut self, max: Duration) -> Duration {
+        Duration::from_nanos((max.as_nanos() as f64 * self.next_f64()) as u64)
+    }
+
+    // generator for another task that does not share its sequence with this one
+    pub fn fork(&mut self) -> JitterRng {
+        JitterRng::new(self.next_u64())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let mut a = JitterRng::new(42);
+        let mut b = JitterRng::new(42);
+        let a: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        let b: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+        assert_eq!(a, b);
+        assert_ne!(JitterRng::new(1).next_u64(), JitterRng::new(2).next_u64());
+    }
+
+    #[test]
+    fn next_f64() {
+        let mut rng = JitterRng::new(7);
+        for _ in 0..1000 {
+            let value = rng.next_f64();
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+}
@@ -1,23 +1,27 @@
 use std::fmt;
 use std::error::Error;
 use std::any::Any;
-use time::Duration;
+use std::time::Duration;
 
 use time_source::*;
 use scheduler::*;
+use task_queue::*;
+use duration::*;
 
 pub enum AbortableWaitError<Token> {
     Empty,
     Overrun(Vec<Token>),
-    Aborted
+    Aborted,
+    ClockJump(ClockOffset)
 }
 
 impl<Token> PartialEq for AbortableWaitError<Token> where Token: PartialEq<Token> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (&AbortableWaitError::Empty, &AbortableWaitError::Empty) => true,
-            (&AbortableWaitError::Overrun(ref tokens), &AbortableWaitError::Overrun(ref other_tokens)) => tokens == other_tokens,
-            (&AbortableWaitError::Aborted, &AbortableWaitError::Aborted) => true,
+            (AbortableWaitError::Empty, AbortableWaitError::Empty) => true,
+            (AbortableWaitError::Overrun(tokens), AbortableWaitError::Overrun(other_tokens)) => tokens == other_tokens,
+            (AbortableWaitError::ClockJump(offset), AbortableWaitError::ClockJump(other_offset)) => offset == other_offset,
+            (AbortableWaitError::Aborted, AbortableWaitError::Aborted) => true,
             _ => false
         }
     }
@@ -26,9 +30,10 @@ impl<Token> PartialEq for AbortableWaitError<Token> where Token: PartialEq<Token
 impl<Token> fmt::Debug for AbortableWaitError<Token> where Token: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &AbortableWaitError::Empty => write!(f, "AbortableWaitError::Empty"),
-            &AbortableWaitError::Overrun(ref tokens) => write!(f, "AbortableWaitError::Overrun({:?})", tokens),
-            &AbortableWaitError::Aborted => write!(f, "AbortableWaitError::Aborted")
+            AbortableWaitError::Empty => write!(f, "AbortableWaitError::Empty"),
+            AbortableWaitError::Overrun(tokens) => write!(f, "AbortableWaitError::Overrun({:?})", tokens),
+            AbortableWaitError::ClockJump(offset) => write!(f, "AbortableWaitError::ClockJump({:?})", offset),
+            AbortableWaitError::Aborted => write!(f, "AbortableWaitError::Aborted")
         }
     }
 }
@@ -36,9 +41,10 @@ impl<Token> fmt::Debug for AbortableWaitError<Token> where Token: fmt::Debug {
 impl<Token> fmt::Display for AbortableWaitError<Token> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &AbortableWaitError::Empty => write!(f, "scheduler is empty"),
-            &AbortableWaitError::Overrun(ref tokens) => write!(f, "scheduler overrun {} tokens", tokens.len()),
-            &AbortableWaitError::Aborted => write!(f, "wait operation was aborted from another thread")
+            AbortableWaitError::Empty => write!(f, "scheduler is empty"),
+            AbortableWaitError::Overrun(tokens) => write!(f, "scheduler overrun {} tokens", tokens.len()),
+            AbortableWaitError::ClockJump(offset) => write!(f, "wall clock jumped {}", offset),
+            AbortableWaitError::Aborted => write!(f, "wait operation was aborted from another thread")
         }
     }
 }
@@ -53,16 +59,18 @@ pub enum AbortableWaitTimeoutError<Token> {
     Empty,
     Timeout,
     Overrun(Vec<Token>),
-    Aborted
+    Aborted,
+    ClockJump(ClockOffset)
 }
 
 impl<Token> PartialEq for AbortableWaitTimeoutError<Token> where Token: PartialEq<Token> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (&AbortableWaitTimeoutError::Empty, &AbortableWaitTimeoutError::Empty) => true,
-            (&AbortableWaitTimeoutError::Timeout, &AbortableWaitTimeoutError::Timeout) => true,
-            (&AbortableWaitTimeoutError::Overrun(ref tokens), &AbortableWaitTimeoutError::Overrun(ref other_tokens)) => tokens == other_tokens,
-            (&AbortableWaitTimeoutError::Aborted, &AbortableWaitTimeoutError::Aborted) => true,
+            (AbortableWaitTimeoutError::Empty, AbortableWaitTimeoutError::Empty) => true,
+            (AbortableWaitTimeoutError::Timeout, AbortableWaitTimeoutError::Timeout) => true,
+            (AbortableWaitTimeoutError::Overrun(tokens), AbortableWaitTimeoutError::Overrun(other_tokens)) => tokens == other_tokens,
+            (AbortableWaitTimeoutError::ClockJump(offset), AbortableWaitTimeoutError::ClockJump(other_offset)) => offset == other_offset,
+            (AbortableWaitTimeoutError::Aborted, AbortableWaitTimeoutError::Aborted) => true,
             _ => false
         }
     }
@@ -71,10 +79,11 @@ impl<Token> PartialEq for AbortableWaitTimeoutError<Token> where Token: PartialE
 impl<Token> fmt::Debug for AbortableWaitTimeoutError<Token> where Token: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &AbortableWaitTimeoutError::Empty => write!(f, "AbortableWaitTimeoutError::Empty"),
-            &AbortableWaitTimeoutError::Timeout => write!(f, "AbortableWaitTimeoutError::Timeout"),
-            &AbortableWaitTimeoutError::Overrun(ref tokens) => write!(f, "AbortableWaitTimeoutError::Overrun({:?})", tokens),
-            &AbortableWaitTimeoutError::Aborted => write!(f, "AbortableWaitTimeoutError::Aborted")
+            AbortableWaitTimeoutError::Empty => write!(f, "AbortableWaitTimeoutError::Empty"),
+            AbortableWaitTimeoutError::Timeout => write!(f, "AbortableWaitTimeoutError::Timeout"),
+            AbortableWaitTimeoutError::Overrun(tokens) => write!(f, "AbortableWaitTimeoutError::Overrun({:?})", tokens),
+            AbortableWaitTimeoutError::ClockJump(offset) => write!(f, "AbortableWaitTimeoutError::ClockJump({:?})", offset),
+            AbortableWaitTimeoutError::Aborted => write!(f, "AbortableWaitTimeoutError::Aborted")
         }
     }
 }
@@ -82,10 +91,11 @@ impl<Token> fmt::Debug for AbortableWaitTimeoutError<Token> where Token: fmt::De
 impl<Token> fmt::Display for AbortableWaitTimeoutError<Token> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &AbortableWaitTimeoutError::Empty => write!(f, "scheduler is empty"),
-            &AbortableWaitTimeoutError::Timeout => write!(f, "timedout while waiting for tokens"),
-            &AbortableWaitTimeoutError::Overrun(ref tokens) => write!(f, "scheduler overrun {} tokens", tokens.len()),
-            &AbortableWaitTimeoutError::Aborted => write!(f, "wait operation was aborted from another thread")
+            AbortableWaitTimeoutError::Empty => write!(f, "scheduler is empty"),
+            AbortableWaitTimeoutError::Timeout => write!(f, "timedout while waiting for tokens"),
+            AbortableWaitTimeoutError::Overrun(tokens) => write!(f, "scheduler overrun {} tokens", tokens.len()),
+            AbortableWaitTimeoutError::ClockJump(offset) => write!(f, "wall clock jumped {}", offset),
+            AbortableWaitTimeoutError::Aborted => write!(f, "wait operation was aborted from another thread")
         }
     }
 }
@@ -96,12 +106,12 @@ impl<Token> Error for AbortableWaitTimeoutError<Token> where Token: fmt::Debug +
     }
 }
 
-impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource + Wait + AbortableWait, Token: Clone {
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource + Wait + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
     pub fn abort_handle(&self) -> <TS as AbortableWait>::AbortHandle {
         self.time_source.abort_handle()
     }
 
-    pub fn abortable_wait(&mut self) -> Result<Vec<Token>, AbortableWaitError<Token>> {
+    pub fn abortable_wait(&mut self) -> Result<Vec<Fired<Token>>, AbortableWaitError<Fired<Token>>> {
         match self.next() {
             Some(schedule) => match schedule {
                 Schedule::NextIn(duration) => {
@@ -113,6 +123,9 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource + Wait + AbortableWait
                 Schedule::Overrun(overrun_tokens) => {
                     Err(AbortableWaitError::Overrun(overrun_tokens))
                 },
+                Schedule::ClockJump(offset) => {
+                    Err(AbortableWaitError::ClockJump(offset))
+                },
                 Schedule::Current(tokens) => {
                     Ok(tokens)
                 }
@@ -121,7 +134,8 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource + Wait + AbortableWait
         }
     }
 
-    pub fn abortable_wait_timeout(&mut self, timeout: Duration) -> Result<Vec<Token>, AbortableWaitTimeoutError<Token>> {
+    pub fn abortable_wait_timeout<D>(&mut self, timeout: D) -> Result<Vec<Fired<Token>>, AbortableWaitTimeoutError<Fired<Token>>> where D: IntoDuration {
+        let timeout = timeout_duration(timeout);
         match self.next() {
             Some(schedule) => match schedule {
                 Schedule::NextIn(duration) => {
@@ -131,12 +145,17 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource + Wait + AbortableWait
                         };
                         return Err(AbortableWaitTimeoutError::Timeout);
                     }
-                    self.time_source.wait(duration);
-                    self.abortable_wait_timeout(Duration::zero())
+                    if let Err(WaitAbortedError) = self.time_source.abortable_wait(duration) {
+                        return Err(AbortableWaitTimeoutError::Aborted);
+                    };
+                    self.abortable_wait_timeout(Duration::new(0, 0))
                 },
                 Schedule::Overrun(overrun_tokens) => {
                     Err(AbortableWaitTimeoutError::Overrun(overrun_tokens))
                 },
+                Schedule::ClockJump(offset) => {
+                    Err(AbortableWaitTimeoutError::ClockJump(offset))
+                },
                 Schedule::Current(tokens) => {
                     Ok(tokens)
                 }
@@ -149,106 +168,123 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource + Wait + AbortableWait
 #[cfg(test)]
 mod test {
     use super::*;
-    use time_source::*;
     use steady_time_source::*;
     use test_helpers::*;
-    use scheduler::*;
-    use time::Duration;
-    use std::thread::spawn;
+    use std::time::{Duration, Instant};
+    use std::thread::{spawn, sleep};
 
     #[test]
     fn abortable_wait_with_aborted_steady_time_source() {
-        let mut scheduler = Scheduler::with_time_source(Duration::milliseconds(100), SteadyTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(100), SteadyTimeSource::new());
 
-        scheduler.after(Duration::milliseconds(100), 0);
-        scheduler.after(Duration::seconds(20), 1);
-        scheduler.after(Duration::seconds(40), 2);
+        scheduler.after(Duration::from_millis(100), 0);
+        scheduler.after(Duration::from_secs(20), 1);
+        scheduler.after(Duration::from_secs(40), 2);
 
         let abort_handle = scheduler.abort_handle();
 
-        assert_eq!(scheduler.abortable_wait(), Ok(vec![0]));
+        assert_eq!(scheduler.abortable_wait().tokens(), Ok(vec![0]));
 
         spawn(move || {
             abort_handle.abort();
         });
 
-        assert_eq!(scheduler.abortable_wait(), Err(AbortableWaitError::Aborted));
+        assert_eq!(scheduler.abortable_wait().tokens(), Err(AbortableWaitError::Aborted));
     }
 
     #[test]
     fn abortable_wait_with_empty() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
-        assert_eq!(scheduler.abortable_wait(), Err(AbortableWaitError::Empty));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
+        assert_eq!(scheduler.abortable_wait().tokens(), Err(AbortableWaitError::Empty));
 
-        scheduler.after(Duration::seconds(0), 0);
-        assert_eq!(scheduler.abortable_wait(), Ok(vec![0]));
-        assert_eq!(scheduler.abortable_wait(), Err(AbortableWaitError::Empty));
+        scheduler.after(Duration::from_secs(0), 0);
+        assert_eq!(scheduler.abortable_wait().tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.abortable_wait().tokens(), Err(AbortableWaitError::Empty));
     }
 
     #[test]
     fn abortable_wait_with_with_overrun() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        scheduler.fast_forward(Duration::seconds(2));
-        assert_eq!(scheduler.abortable_wait(), Err(AbortableWaitError::Overrun(vec![0, 1])));
-        assert_eq!(scheduler.abortable_wait(), Ok(vec![2]));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.abortable_wait().tokens(), Err(AbortableWaitError::Overrun(vec![0, 1])));
+        assert_eq!(scheduler.abortable_wait().tokens(), Ok(vec![2]));
     }
 
     #[test]
     fn abortable_wait_timeout_with_aborted_steady_time_source() {
-        let mut scheduler = Scheduler::with_time_source(Duration::milliseconds(100), SteadyTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(100), SteadyTimeSource::new());
 
-        scheduler.after(Duration::milliseconds(100), 0);
-        scheduler.after(Duration::seconds(20), 1);
-        scheduler.after(Duration::seconds(40), 2);
+        scheduler.after(Duration::from_millis(100), 0);
+        scheduler.after(Duration::from_secs(20), 1);
+        scheduler.after(Duration::from_secs(40), 2);
 
         let abort_handle = scheduler.abort_handle();
 
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(2)), Ok(vec![0]));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(2)).tokens(), Ok(vec![0]));
+
+        spawn(move || {
+            abort_handle.abort();
+        });
+
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(2)).tokens(), Err(AbortableWaitTimeoutError::Aborted));
+    }
+
+    #[test]
+    fn abortable_wait_timeout_aborted_before_task_is_due() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(100), SteadyTimeSource::new());
+
+        // task is due before the timeout
+        scheduler.after(Duration::from_secs(2), 0);
+        let abort_handle = scheduler.abort_handle();
 
+        let start = Instant::now();
         spawn(move || {
+            sleep(Duration::from_millis(50));
             abort_handle.abort();
         });
 
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(2)), Err(AbortableWaitTimeoutError::Aborted));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(10)).tokens(), Err(AbortableWaitTimeoutError::Aborted));
+        assert!(Instant::now() - start < Duration::from_secs(1));
+        assert!(scheduler.next_in() > Duration::from_secs(1));
     }
 
     #[test]
     fn abortable_wait_timeout_with_empty() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(4)), Err(AbortableWaitTimeoutError::Empty));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(4)).tokens(), Err(AbortableWaitTimeoutError::Empty));
 
-        scheduler.after(Duration::seconds(0), 0);
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(4)), Ok(vec![0]));
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(4)), Err(AbortableWaitTimeoutError::Empty));
+        scheduler.after(Duration::from_secs(0), 0);
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(4)).tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(4)).tokens(), Err(AbortableWaitTimeoutError::Empty));
     }
 
     #[test]
     fn abortable_wait_timeout_with_overrun() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        scheduler.fast_forward(Duration::seconds(2));
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(4)), Err(AbortableWaitTimeoutError::Overrun(vec![0, 1])));
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(4)), Ok(vec![2]));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(4)).tokens(), Err(AbortableWaitTimeoutError::Overrun(vec![0, 1])));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(4)).tokens(), Ok(vec![2]));
     }
 
     #[test]
     fn abortable_wait_timeout_with_timeout() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::seconds(2)), Ok(vec![0]));
-        assert_eq!(scheduler.abortable_wait_timeout(Duration::milliseconds(500)), Err(AbortableWaitTimeoutError::Timeout));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_secs(2)).tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.abortable_wait_timeout(Duration::from_millis(500)).tokens(), Err(AbortableWaitTimeoutError::Timeout));
     }
 }
@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use time_source::*;
+use scheduler::*;
+use task_queue::*;
+use task::*;
+use duration::*;
+
+// How to schedule task for an instant that has already passed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PastInstantPolicy {
+    // keep the instant so the task is reported with overrun tasks
+    Overrun,
+    // move the instant to now so the task is reported as current
+    Current
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    // run once at given time of the time source
+    pub fn at<D>(&mut self, instant: D, token: Token) -> TaskId where D: IntoDuration {
+        self.try_at(instant, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_at<D>(&mut self, instant: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let instant = instant.into_duration()?;
+        self.schedule_at(instant, TaskBond::OneOff, token)
+    }
+
+    fn schedule_at(&mut self, instant: Duration, bond: TaskBond, token: Token) -> Result<TaskId, SchedulerError> {
+        let now = self.time_source.now();
+        if instant >= now {
+            // future instant has to fit in time points
+            self.try_to_time_point(instant)?;
+        }
+        let id = self.next_task_id();
+
+        let task = if instant >= now {
+            Task::new(id, instant - now, now, bond, token)?
+        } else {
+            let instant = match self.past_instant_policy {
+                PastInstantPolicy::Overrun => instant,
+                PastInstantPolicy::Current => now
+            };
+            Task::new(id, Duration::new(0, 0), instant, bond, token)?
+        };
+        self.schedule(task);
+        Ok(id)
+    }
+
+    pub fn set_past_instant_policy(&mut self, policy: PastInstantPolicy) {
+        self.past_instant_policy = policy;
+    }
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: InstantClock, Token: Clone, Q: TaskQueue<Token> {
+    // run once at given instant; instant before time source zero is taken for time source zero
+    pub fn at_instant(&mut self, instant: Instant, token: Token) -> TaskId {
+        self.try_at_instant(instant, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_at_instant(&mut self, instant: Instant, token: Token) -> Result<TaskId, SchedulerError> {
+        let instant = self.time_source.time_at(instant).unwrap_or_default();
+        self.try_at(instant, token)
+    }
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: WallClock, Token: Clone, Q: TaskQueue<Token> {
+    // Run once at given wall clock time; time before time source zero is taken for time source zero.
+    // Task keeps the wall clock time when time source epoch is re-anchored.
+    pub fn at_time(&mut self, time: SystemTime, token: Token) -> TaskId {
+        self.try_at_time(time, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_at_time(&mut self, time: SystemTime, token: Token) -> Result<TaskId, SchedulerError> {
+        let instant = time.duration_since(self.time_source.epoch()).unwrap_or_default();
+        self.schedule_at(instant, TaskBond::At(time), token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn at() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.fast_forward(Duration::from_secs(10));
+
+        scheduler.at(Duration::from_secs(12), 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(2))));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+    }
+
+    #[test]
+    fn at_past_instant() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.fast_forward(Duration::from_secs(10));
+
+        scheduler.at(Duration::from_secs(5), 1);
+        scheduler.at(Duration::new(0, 0), 2);
+        scheduler.at(Duration::from_secs(10), 3);
+        match scheduler.next() {
+            Some(Schedule::Overrun(fired)) => {
+                assert_eq!(fired.iter().map(|fired| fired.token).collect::<Vec<_>>(), vec![2, 1]);
+                assert_eq!(fired[0].scheduled, Duration::new(0, 0));
+                assert_eq!(fired[1].lateness, Duration::from_secs(5));
+            },
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+        // instant of now
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![3])));
+
+        scheduler.set_past_instant_policy(PastInstantPolicy::Current);
+        scheduler.at(Duration::from_secs(5), 4);
+        scheduler.at(Duration::new(0, 0), 5);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![4, 5])));
+        assert_eq!(scheduler.next().tokens(), None);
+    }
+
+    #[test]
+    fn at_instant() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let origin = scheduler.time_source.clock().origin();
+        scheduler.fast_forward(Duration::from_secs(10));
+
+        scheduler.at_instant(origin + Duration::from_secs(12), 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(2))));
+
+        // before time source zero
+        scheduler.at_instant(origin - Duration::from_secs(5), 2);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2])));
+
+        assert_eq!(scheduler.try_at_instant(origin + Duration::from_secs(600 * 365 * 24 * 3600), 3), Err(SchedulerError::Overflow));
+    }
+
+    #[test]
+    fn at_time() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.fast_forward(Duration::from_secs(10));
+
+        // MockTimeSource epoch is UNIX epoch
+        scheduler.at_time(UNIX_EPOCH + Duration::from_secs(70), 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(60))));
+
+        scheduler.at_time(UNIX_EPOCH + Duration::from_secs(5), 2);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2])));
+    }
+
+    #[test]
+    fn at_time_clock_jump() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let clock = scheduler.time_source.clock();
+        scheduler.at_time(UNIX_EPOCH + Duration::from_secs(60), 1);
+        scheduler.after(Duration::from_secs(90), 2);
+
+        // wall clock is now 00:00:20 instead of 00:00:00; relative task is not affected
+        clock.jump_clock(ClockJump::Reanchor(ClockOffset::Forward(Duration::from_secs(20))));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(40))));
+
+        // wall clock is now 00:00:10
+        clock.jump_clock(ClockJump::Report(ClockOffset::Backward(Duration::from_secs(10))));
+        let offset = match scheduler.next() {
+            Some(Schedule::ClockJump(offset)) => offset,
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        };
+        scheduler.reanchor(offset);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(50))));
+
+        scheduler.fast_forward(Duration::from_secs(50));
+        match scheduler.next() {
+            Some(Schedule::Current(fired)) => assert_eq!(scheduler.time_source.epoch() + fired[0].scheduled, UNIX_EPOCH + Duration::from_secs(60)),
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(40))));
+    }
+}
@@ -0,0 +1,166 @@
+use time_source::*;
+use scheduler::*;
+use task_queue::*;
+use cron::*;
+use task::*;
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: WallClock, Token: Clone, Q: TaskQueue<Token> {
+    pub fn cron(&mut self, expression: &str, token: Token) -> Result<TaskId, CronError> {
+        let schedule: CronSchedule = expression.parse()?;
+
+        let epoch = self.time_source.epoch();
+        let now = self.time_source.now();
+        let wall_clock_now = epoch.checked_add(now).ok_or(CronError::Overflow)?;
+        let next = schedule.next_after(wall_clock_now).ok_or(CronError::NeverFires)?;
+        let interval = next.duration_since(wall_clock_now).unwrap_or_default();
+        // next run has to fit in time points
+        self.check_interval(interval).map_err(|_| CronError::Overflow)?;
+
+        let id = self.next_task_id();
+        let task = Task::new(id, interval, now, TaskBond::Cron(schedule, epoch), token).map_err(|_| CronError::Overflow)?;
+        self.schedule(task);
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn cron() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.cron("*/5 * * * *", 1).unwrap();
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(5 * 60))));
+
+        scheduler.fast_forward(Duration::from_secs(3 * 60));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(2 * 60))));
+
+        scheduler.fast_forward(Duration::from_secs(2 * 60));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(5 * 60))));
+    }
+
+    #[test]
+    fn cron_with_overrun() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.fast_forward(Duration::from_secs(30));
+        scheduler.cron("0 * * * *", 1).unwrap();
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(59 * 60) + Duration::from_secs(30))));
+
+        scheduler.fast_forward(Duration::from_secs(3 * 60 * 60));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![1, 1, 1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(59 * 60) + Duration::from_secs(30))));
+    }
+
+    #[test]
+    fn cron_errors() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        assert_eq!(scheduler.cron("* * *", 1), Err(CronError::FieldCount(3)));
+        assert_eq!(scheduler.cron("0 24 * * *", 1), Err(CronError::OutOfRange(CronField::Hour, 24)));
+        assert_eq!(scheduler.cron("0 0 31 feb *", 1), Err(CronError::NeverFires));
+        assert_eq!(scheduler.next().tokens(), None);
+
+        // next run does not fit in time points
+        scheduler.fast_forward(Duration::from_nanos(u64::MAX));
+        assert_eq!(scheduler.cron("* * * * *", 1), Err(CronError::Overflow));
+        // wall clock time cannot be represented
+        scheduler.fast_forward(Duration::from_secs(u64::MAX / 2));
+        assert_eq!(scheduler.cron("* * * * *", 1), Err(CronError::Overflow));
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn cron_clock_jump_reanchor() {
+        let mut time_source = MockTimeSource::new();
+        time_source.fast_forward(Duration::from_secs(10 * 60));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), time_source);
+
+        scheduler.cron("0 * * * *", 1).unwrap();
+        scheduler.after(Duration::from_secs(40 * 60), 2);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(40 * 60))));
+
+        // wall clock is now 00:30 instead of 00:10; relative tasks are not affected
+        scheduler.time_source.jump_clock(ClockJump::Reanchor(ClockOffset::Forward(Duration::from_secs(20 * 60))));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(30 * 60))));
+
+        scheduler.fast_forward(Duration::from_secs(30 * 60));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(10 * 60))));
+
+        // wall clock is now 00:40 instead of 01:10; 01:00 run already happened so next is still at 02:00
+        scheduler.fast_forward(Duration::from_secs(10 * 60));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+        scheduler.time_source.jump_clock(ClockJump::Reanchor(ClockOffset::Backward(Duration::from_secs(30 * 60))));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(80 * 60))));
+    }
+
+    #[test]
+    fn cron_clock_jump_paused() {
+        let mut time_source = MockTimeSource::new();
+        time_source.fast_forward(Duration::from_secs(10 * 60));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), time_source);
+
+        let id = scheduler.cron("0 * * * *", 1).unwrap();
+        assert!(scheduler.pause_task(id));
+
+        // wall clock is now 00:30 instead of 00:10 so 01:00 run is 30 minutes away once resumed
+        scheduler.time_source.jump_clock(ClockJump::Reanchor(ClockOffset::Forward(Duration::from_secs(20 * 60))));
+        assert_eq!(scheduler.next().tokens(), None);
+        assert!(scheduler.resume_task(id));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(30 * 60))));
+
+        // schedule stays at the last time point instead of overflowing
+        let id = scheduler.at_time(scheduler.time_source.epoch() + Duration::from_nanos(u64::MAX), 2);
+        scheduler.reanchor(ClockOffset::Backward(Duration::from_secs(60 * 60)));
+        assert_eq!(scheduler.task(id).unwrap().schedule(), Duration::from_nanos(u64::MAX));
+    }
+
+    #[test]
+    fn cron_clock_jump_beyond_time_source_zero() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        // wall clock jumps from 00:00 to 02:00 so 00:30 run is now overdue
+        scheduler.cron("30 * * * *", 1).unwrap();
+        scheduler.time_source.jump_clock(ClockJump::Reanchor(ClockOffset::Forward(Duration::from_secs(2 * 60 * 60))));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(30 * 60))));
+    }
+
+    #[test]
+    fn cron_clock_jump_report() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.cron("0 * * * *", 1).unwrap();
+        scheduler.time_source.jump_clock(ClockJump::Report(ClockOffset::Forward(Duration::from_secs(20 * 60))));
+        let offset = match scheduler.next() {
+            Some(Schedule::ClockJump(offset)) => offset,
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        };
+        assert_eq!(offset, ClockOffset::Forward(Duration::from_secs(20 * 60)));
+        // not re-anchored yet
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(60 * 60))));
+
+        // wall clock is now 00:20 so 01:00 run is 40 minutes away
+        scheduler.reanchor(offset);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(40 * 60))));
+        scheduler.fast_forward(Duration::from_secs(40 * 60));
+        match scheduler.next() {
+            Some(Schedule::Current(fired)) => assert_eq!(scheduler.time_source.epoch() + fired[0].scheduled, UNIX_EPOCH + Duration::from_secs(60 * 60)),
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(60 * 60))));
+    }
+
+    #[test]
+    fn cron_steady_time_source() {
+        let mut scheduler = Scheduler::new(Duration::from_secs(1));
+        scheduler.cron("* * * * *", 1).unwrap();
+        assert!(scheduler.next_in() <= Duration::from_secs(60));
+    }
+}
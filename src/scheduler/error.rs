@@ -0,0 +1,48 @@
+use std::fmt;
+use std::error::Error;
+use std::time::Duration;
+
+use backoff::BackoffError;
+use task::TaskId;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchedulerError {
+    // time point interval has to be positive and fit in nanoseconds
+    InvalidTimePointInterval(Duration),
+    TaskQueueNotEmpty,
+    // std::time::Duration cannot represent negative duration; magnitude of given one
+    NegativeDuration(Duration),
+    // time or its time point does not fit in nanoseconds
+    Overflow,
+    // task has to run at least once
+    InvalidCount,
+    // recurring task would run at the same time over and over with zero interval
+    InvalidInterval,
+    InvalidBackoff(BackoffError),
+    // restored snapshot has more than one task with the same id
+    DuplicateTaskId(TaskId),
+    // scheduler thread has stopped
+    Disconnected
+}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchedulerError::InvalidTimePointInterval(interval) => write!(f, "time point interval must be positive Duration that fits in nanoseconds but got {:?}", interval),
+            SchedulerError::TaskQueueNotEmpty => write!(f, "task queue must be empty"),
+            SchedulerError::NegativeDuration(duration) => write!(f, "negative duration -{:?} cannot be converted to std::time::Duration", duration),
+            SchedulerError::Overflow => write!(f, "time is too far in the future"),
+            SchedulerError::InvalidCount => write!(f, "count of runs must be positive"),
+            SchedulerError::InvalidInterval => write!(f, "interval of recurring task must be positive"),
+            SchedulerError::InvalidBackoff(err) => write!(f, "invalid backoff: {}", err),
+            SchedulerError::DuplicateTaskId(id) => write!(f, "more than one task with id {}", id.0),
+            SchedulerError::Disconnected => write!(f, "scheduler thread has stopped")
+        }
+    }
+}
+
+impl Error for SchedulerError {
+    fn description(&self) -> &str {
+        "problem while scheduling task"
+    }
+}
@@ -0,0 +1,259 @@
+use time_source::*;
+use scheduler::*;
+use task_queue::*;
+use task::*;
+use duration::*;
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    // number of tasks including paused ones and ones waiting for acknowledgement
+    pub fn len(&self) -> usize {
+        self.tasks.len() + self.parked.len() + self.paused.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // All tasks: scheduled ones ordered by when they are due (see Task::schedule()), then ones waiting for
+    // acknowledgement and then paused ones, both ordered by task id
+    pub fn tasks(&self) -> Vec<&Task<Token>> {
+        let mut tasks = self.tasks.tasks();
+        tasks.sort_by_key(|task| (task.schedule(), task.id));
+        let mut parked: Vec<&Task<Token>> = self.parked.values().collect();
+        parked.sort_by_key(|task| task.id);
+        let mut paused: Vec<&Task<Token>> = self.paused.values().map(|paused| &paused.task).collect();
+        paused.sort_by_key(|task| task.id);
+
+        tasks.extend(parked);
+        tasks.extend(paused);
+        tasks
+    }
+
+    // Tasks that next() will fire with the next batch: all overdue tasks if any, otherwise tasks due in
+    // the first time point, in order they will be fired. Runs of overdue tasks that are due before now are
+    // not included.
+    pub fn peek(&self) -> Vec<&Task<Token>> {
+        self.try_peek().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_peek(&self) -> Result<Vec<&Task<Token>>, SchedulerError> {
+        let current_time_point = self.try_to_time_point(self.time_source.now())?;
+        let first_time_point = match self.tasks.first_time_point() {
+            Some(time_point) => time_point,
+            None => return Ok(Vec::new())
+        };
+
+        let mut tasks: Vec<&Task<Token>> = self.tasks.tasks().into_iter().filter(|task| {
+            let time_point = self.to_time_point(task.schedule());
+            if first_time_point < current_time_point {
+                time_point < current_time_point
+            } else {
+                time_point == first_time_point
+            }
+        }).collect();
+        tasks.sort_by_key(|task| fire_order(task));
+        Ok(tasks)
+    }
+
+    // Every run of scheduled tasks due before given time from now ordered by when they are due and task id.
+    // Overdue runs are listed as next() will report them according to overrun policy of the task; fixed delay
+    // task has only its next run listed as following ones depend on acknowledgement. Each run is represented
+    // by the task as it will be when the run fires.
+    pub fn forecast<D>(&self, window: D) -> Vec<Task<Token>> where D: IntoDuration {
+        self.try_forecast(window).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_forecast<D>(&self, window: D) -> Result<Vec<Task<Token>>, SchedulerError> where D: IntoDuration {
+        let window = window.into_duration()?;
+        let now = self.time_source.now();
+        let end = now.checked_add(window).ok_or(SchedulerError::Overflow)?;
+        // tasks due before current time point are overdue
+        let current = self.to_duration(self.try_to_time_point(now)?);
+        let mut runs = Vec::new();
+
+        for task in self.tasks.tasks() {
+            if let TaskBond::FixedDelay = task.bond {
+                // overdue or not it runs once and waits for acknowledgement
+                if task.schedule() < end {
+                    runs.push(task.clone());
+                }
+                continue;
+            }
+
+            let mut next = Some(task.clone());
+            match task.overrun_policy {
+                // every missed run is reported on its own like any other run
+                OverrunPolicy::CatchUp => (),
+                _ if task.schedule() >= current => (),
+                _ => {
+                    let mut fired = Vec::new();
+                    next = self.reschedule_overrun(task.clone(), now, &mut fired);
+                    if !fired.is_empty() {
+                        runs.push(task.clone());
+                    }
+                }
+            }
+
+            while let Some(task) = next.take() {
+                if task.schedule() >= end {
+                    break;
+                }
+                let regular_schedule = task.regular_schedule();
+                next = self.schedulable(task.clone().reschedule());
+                runs.push(task);
+
+                // task with zero interval would run forever at the same time
+                match next {
+                    Some(ref next) if next.regular_schedule() <= regular_schedule => break,
+                    _ => ()
+                }
+            }
+        }
+
+        runs.sort_by_key(|task| (task.schedule(), task.id));
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::time::Duration;
+
+    fn tokens(tasks: Vec<&Task<i32>>) -> Vec<i32> {
+        tasks.into_iter().map(|task| task.token).collect()
+    }
+
+    #[test]
+    fn len() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert!(scheduler.is_empty());
+
+        let id = scheduler.after(Duration::from_secs(1), 1);
+        scheduler.every_after_ack(Duration::from_secs(1), 2);
+        scheduler.pause_task(id);
+        assert_eq!(scheduler.len(), 2);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+        assert_eq!(scheduler.len(), 2);
+
+        scheduler.cancel_task(id);
+        assert_eq!(scheduler.len(), 1);
+        assert!(!scheduler.is_empty());
+    }
+
+    #[test]
+    fn tasks() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.after(Duration::from_secs(3), 1);
+        scheduler.every(Duration::from_secs(2), 2);
+        scheduler.after(Duration::from_secs(2), 3);
+
+        let tasks = scheduler.tasks();
+        assert_eq!(tasks.iter().map(|task| task.schedule()).collect::<Vec<_>>(),
+            vec![Duration::from_secs(2), Duration::from_secs(2), Duration::from_secs(3)]);
+        assert_eq!(tokens(tasks), vec![2, 3, 1]);
+
+        // paused and waiting for acknowledgement tasks come after scheduled ones
+        let paused = scheduler.after(Duration::from_secs(1), 4);
+        scheduler.pause_task(paused);
+        scheduler.every_after_ack(Duration::from_secs(1), 5);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![5])));
+        assert_eq!(tokens(scheduler.tasks()), vec![2, 3, 1, 5, 4]);
+        assert_eq!(scheduler.tasks().len(), scheduler.len());
+    }
+
+    #[test]
+    fn peek() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert!(scheduler.peek().is_empty());
+
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
+        scheduler.after(Duration::from_secs(3), 3);
+        assert_eq!(tokens(scheduler.peek()), vec![1]);
+
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(tokens(scheduler.peek()), vec![1]);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![1])));
+        assert_eq!(tokens(scheduler.peek()), vec![2]);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+
+        // in order the batch is fired
+        scheduler.after(Duration::from_secs(1), 4);
+        let id = scheduler.after(Duration::from_secs(1), 5);
+        assert!(scheduler.set_priority(id, 1));
+        assert_eq!(tokens(scheduler.peek()), vec![5, 3, 4]);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![5, 3, 4])));
+    }
+
+    #[test]
+    fn forecast() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.every(Duration::from_secs(2), 1);
+        scheduler.after(Duration::from_secs(3), 2);
+        scheduler.every_times(Duration::from_secs(1), 2, 3);
+        scheduler.after(Duration::new(0, 0), 4);
+        scheduler.fast_forward(Duration::from_secs(1));
+
+        let forecast = scheduler.forecast(Duration::from_secs(4));
+        assert_eq!(forecast.iter().map(|task| (task.token, task.schedule().as_secs())).collect::<Vec<_>>(),
+            vec![(4, 0), (3, 1), (1, 2), (3, 2), (2, 3), (1, 4)]);
+        assert_eq!(forecast[5].iteration, 1);
+        assert!(scheduler.forecast(Duration::new(0, 0)).iter().all(|task| task.schedule() < Duration::from_secs(1)));
+        assert_eq!(scheduler.try_forecast(Duration::from_secs(u64::MAX)).err(), Some(SchedulerError::Overflow));
+    }
+
+    #[cfg(feature = "legacy-time")]
+    #[test]
+    fn forecast_legacy_duration() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.every(Duration::from_secs(2), 1);
+
+        assert_eq!(scheduler.forecast(::time::Duration::seconds(4)).len(), 1);
+        assert_eq!(scheduler.try_forecast(::time::Duration::seconds(-1)).err(), Some(SchedulerError::NegativeDuration(Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn forecast_overrun() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.every_after_ack(Duration::from_secs(1), 1);
+        for &(token, policy) in &[(2, OverrunPolicy::CatchUp), (3, OverrunPolicy::Coalesce), (4, OverrunPolicy::Skip), (5, OverrunPolicy::Delay)] {
+            let id = scheduler.every(Duration::from_secs(2), token);
+            assert!(scheduler.set_overrun_policy(id, policy));
+        }
+        scheduler.fast_forward(Duration::from_secs(5));
+
+        // 1 waits for acknowledgement and delayed run of 5 is due at 7 seconds past the window
+        let forecast = scheduler.forecast(Duration::from_secs(2));
+        assert_eq!(forecast.iter().map(|task| (task.token, task.schedule().as_secs())).collect::<Vec<_>>(),
+            vec![(1, 1), (2, 2), (3, 2), (5, 2), (2, 4), (2, 6), (3, 6), (4, 6)]);
+
+        let mut fired = Vec::new();
+        while let Some(schedule) = scheduler.next() {
+            match schedule {
+                Schedule::Current(batch) | Schedule::Overrun(batch) => fired.extend(batch.into_iter().map(|fired| (fired.token, fired.scheduled.as_secs()))),
+                Schedule::NextIn(duration) if scheduler.time_source.now() + duration < Duration::from_secs(7) => scheduler.fast_forward(duration),
+                _ => break
+            }
+        }
+        let mut expected: Vec<_> = forecast.iter().map(|task| (task.token, task.schedule().as_secs())).collect();
+        expected.sort();
+        fired.sort();
+        assert_eq!(fired, expected);
+    }
+
+    #[test]
+    fn try_peek_past_time_point_range() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_nanos(1), MockTimeSource::new());
+        scheduler.after(Duration::from_secs(1), 1);
+        assert_eq!(tokens(scheduler.try_peek().unwrap()), vec![1]);
+
+        scheduler.fast_forward(Duration::from_secs(u64::MAX));
+        assert_eq!(scheduler.try_peek().err(), Some(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_forecast(Duration::from_secs(1)).err(), Some(SchedulerError::Overflow));
+    }
+}
@@ -1,20 +1,45 @@
+mod error;
 mod wait;
 mod abortable_wait;
-
+mod cron;
+mod at;
+mod pause;
+mod tags;
+mod inspect;
+mod shared;
+mod spawn;
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(feature = "serde")]
+mod snapshot;
+
+pub use scheduler::error::*;
 pub use scheduler::wait::*;
 pub use scheduler::abortable_wait::*;
-
-use std::collections::BTreeMap;
-use std::cmp::Ordering;
+pub use scheduler::at::*;
+pub use scheduler::shared::*;
+pub use scheduler::spawn::*;
+#[cfg(feature = "stream")]
+pub use scheduler::stream::*;
+#[cfg(feature = "serde")]
+pub use scheduler::snapshot::*;
+
+use std::cmp::{self, Ordering};
 use std::fmt;
 use std::cmp::PartialEq;
-use time::Duration;
+use std::marker::PhantomData;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 use time_source::*;
 use steady_time_source::*;
 use task::*;
-
-type PointInTime = u64;
+use task_queue::*;
+use btree_task_queue::*;
+use backoff::*;
+use rng::*;
+use duration::*;
+use scheduler::pause::PausedTask;
 
 enum SchedulerAction {
     None,
@@ -26,15 +51,19 @@ enum SchedulerAction {
 pub enum Schedule<Token> {
     NextIn(Duration),
     Overrun(Vec<Token>),
-    Current(Vec<Token>)
+    Current(Vec<Token>),
+    // wall clock of the time source was stepped by given offset; cron tasks keep the old wall clock
+    // until re-anchored with Scheduler::reanchor
+    ClockJump(ClockOffset)
 }
 
 impl<Token> PartialEq for Schedule<Token> where Token: PartialEq<Token> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (&Schedule::NextIn(ref duration), &Schedule::NextIn(ref other_duration)) => duration == other_duration,
-            (&Schedule::Overrun(ref tokens), &Schedule::Overrun(ref other_tokens)) => tokens == other_tokens,
-            (&Schedule::Current(ref tokens), &Schedule::Current(ref other_tokens)) => tokens == other_tokens,
+            (Schedule::NextIn(duration), Schedule::NextIn(other_duration)) => duration == other_duration,
+            (Schedule::Overrun(tokens), Schedule::Overrun(other_tokens)) => tokens == other_tokens,
+            (Schedule::Current(tokens), Schedule::Current(other_tokens)) => tokens == other_tokens,
+            (Schedule::ClockJump(offset), Schedule::ClockJump(other_offset)) => offset == other_offset,
             _ => false
         }
     }
@@ -43,67 +72,334 @@ impl<Token> PartialEq for Schedule<Token> where Token: PartialEq<Token> {
 impl<Token> fmt::Debug for Schedule<Token> where Token: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Schedule::NextIn(ref duration) => write!(f, "Schedule::NextIn({}ms)", duration.num_milliseconds()),
-            &Schedule::Overrun(ref tokens) => write!(f, "Schedule::Overrun({:?})", tokens),
-            &Schedule::Current(ref tokens) => write!(f, "Schedule::Current({:?})", tokens),
+            Schedule::NextIn(duration) => write!(f, "Schedule::NextIn({}ms)", duration.as_millis()),
+            Schedule::Overrun(tokens) => write!(f, "Schedule::Overrun({:?})", tokens),
+            Schedule::Current(tokens) => write!(f, "Schedule::Current({:?})", tokens),
+            Schedule::ClockJump(offset) => write!(f, "Schedule::ClockJump({})", offset),
+        }
+    }
+}
+
+// Record of a task run handed out by the scheduler
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fired<Token> {
+    pub id: TaskId,
+    pub token: Token,
+    // when the run was due
+    pub scheduled: Duration,
+    // when the run was handed out
+    pub fired: Duration,
+    // delay between previous run or scheduling of the task and this run
+    pub interval: Duration,
+    // fired - scheduled; zero for runs handed out early as whole time point is handed out at once
+    pub lateness: Duration,
+    // number of runs of the task before this one
+    pub iteration: u64,
+    // number of runs this record stands for; more than one for coalesced or delayed overrun
+    pub runs: u64,
+    // task will not run again
+    pub last: bool
+}
+
+impl<Token> Fired<Token> where Token: Clone {
+    fn new(task: &Task<Token>, now: Duration, runs: u64, last: bool) -> Fired<Token> {
+        Fired {
+            id: task.id,
+            token: task.token.clone(),
+            scheduled: task.schedule(),
+            fired: now,
+            interval: task.interval,
+            lateness: now.checked_sub(task.schedule()).unwrap_or_default(),
+            iteration: task.iteration,
+            runs,
+            last
         }
     }
 }
 
-pub struct Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
+pub struct Scheduler<Token, TS, Q = BTreeTaskQueue<Token>> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
     time_point_interval: Duration,
-    tasks: BTreeMap<PointInTime, Vec<Task<Token>>>,
-    time_source: TS
+    tasks: Q,
+    // fired fixed delay tasks waiting for acknowledgement
+    parked: HashMap<TaskId, Task<Token>>,
+    paused: HashMap<TaskId, PausedTask<Token>>,
+    // time whole scheduler was paused at
+    paused_at: Option<Duration>,
+    // tasks by tag and tags by task
+    tagged: HashMap<String, HashSet<TaskId>>,
+    tags: HashMap<TaskId, Vec<String>>,
+    next_task_id: u64,
+    time_source: TS,
+    // seeds generators of tasks with randomized delays
+    rng: JitterRng,
+    past_instant_policy: PastInstantPolicy,
+    token: PhantomData<Token>
 }
 
 impl<Token> Scheduler<Token, SteadyTimeSource> where Token: Clone {
-    pub fn new(time_point_interval: Duration) -> Scheduler<Token, SteadyTimeSource> {
+    pub fn new<D>(time_point_interval: D) -> Scheduler<Token, SteadyTimeSource> where D: IntoDuration {
         Scheduler::with_time_source(time_point_interval, SteadyTimeSource::new())
     }
 }
 
 impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
-    pub fn with_time_source(time_point_interval: Duration, time_source: TS) -> Scheduler<Token, TS> {
-        assert!(time_point_interval > Duration::seconds(0), "time_point_interval must be positive Duration");
-        Scheduler {
-            time_point_interval: time_point_interval,
-            tasks: BTreeMap::new(),
-            time_source: time_source
+    pub fn with_time_source<D>(time_point_interval: D, time_source: TS) -> Scheduler<Token, TS> where D: IntoDuration {
+        Scheduler::with_task_queue(time_point_interval, time_source, BTreeTaskQueue::new())
+    }
+
+    pub fn try_with_time_source<D>(time_point_interval: D, time_source: TS) -> Result<Scheduler<Token, TS>, SchedulerError> where D: IntoDuration {
+        Scheduler::try_with_task_queue(time_point_interval, time_source, BTreeTaskQueue::new())
+    }
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    pub fn with_task_queue<D>(time_point_interval: D, time_source: TS, task_queue: Q) -> Scheduler<Token, TS, Q> where D: IntoDuration {
+        Scheduler::try_with_task_queue(time_point_interval, time_source, task_queue).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_with_task_queue<D>(time_point_interval: D, time_source: TS, task_queue: Q) -> Result<Scheduler<Token, TS, Q>, SchedulerError> where D: IntoDuration {
+        let time_point_interval = time_point_interval.into_duration()?;
+        if time_point_interval == Duration::new(0, 0) || time_point_interval.as_nanos() > u128::from(u64::MAX) {
+            return Err(SchedulerError::InvalidTimePointInterval(time_point_interval));
+        }
+        if !task_queue.is_empty() {
+            return Err(SchedulerError::TaskQueueNotEmpty);
         }
+        Ok(Scheduler {
+            time_point_interval,
+            tasks: task_queue,
+            parked: HashMap::new(),
+            paused: HashMap::new(),
+            paused_at: None,
+            tagged: HashMap::new(),
+            tags: HashMap::new(),
+            next_task_id: 0,
+            time_source,
+            rng: JitterRng::from_time(),
+            past_instant_policy: PastInstantPolicy::Overrun,
+            token: PhantomData
+        })
+    }
+
+    fn next_task_id(&mut self) -> TaskId {
+        let id = TaskId(self.next_task_id);
+        self.next_task_id += 1;
+        id
     }
 
     fn schedule(&mut self, task: Task<Token>) {
+        if let Some(paused_at) = self.paused_at {
+            // tasks scheduled while paused are paused as well
+            let now = self.time_source.now();
+            self.paused.insert(task.id, PausedTask {
+                task,
+                paused_at: cmp::max(paused_at, now),
+                explicit: false
+            });
+            return;
+        }
+
         let time_point = self.to_time_point(task.schedule());
-        self.tasks.entry(time_point).or_insert(Vec::new()).push(task);
+        self.tasks.insert(time_point, task);
+    }
+
+    // check that task with given interval starting now can be scheduled
+    fn check_interval(&self, interval: Duration) -> Result<(), SchedulerError> {
+        let schedule = self.time_source.now().checked_add(interval).ok_or(SchedulerError::Overflow)?;
+        self.try_to_time_point(schedule).map(|_| ())
+    }
+
+    fn check_recurring_interval(&self, interval: Duration) -> Result<(), SchedulerError> {
+        if interval == Duration::new(0, 0) {
+            return Err(SchedulerError::InvalidInterval);
+        }
+        self.check_interval(interval)
     }
 
-    pub fn after(&mut self, duration: Duration, token: Token) {
-        let task = Task::new(duration, self.time_source.now(), TaskBond::OneOff, token);
+    pub fn after<D>(&mut self, duration: D, token: Token) -> TaskId where D: IntoDuration {
+        self.try_after(duration, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_after<D>(&mut self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let duration = duration.into_duration()?;
+        self.check_interval(duration)?;
+        let id = self.next_task_id();
+        let task = Task::new(id, duration, self.time_source.now(), TaskBond::OneOff, token)?;
         self.schedule(task);
+        Ok(id)
     }
 
-    pub fn every(&mut self, duration: Duration, token: Token) {
-        let task = Task::new(duration, self.time_source.now(), TaskBond::Perpetual, token);
+    pub fn every<D>(&mut self, duration: D, token: Token) -> TaskId where D: IntoDuration {
+        self.try_every(duration, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every<D>(&mut self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let duration = duration.into_duration()?;
+        self.check_recurring_interval(duration)?;
+        let id = self.next_task_id();
+        let task = Task::new(id, duration, self.time_source.now(), TaskBond::Perpetual, token)?;
         self.schedule(task);
+        Ok(id)
+    }
+
+    // Runs with given delay between acknowledgement of previous run and the next run.
+    // Fired task is not scheduled again until ack() is called with its id.
+    pub fn every_after_ack<D>(&mut self, duration: D, token: Token) -> TaskId where D: IntoDuration {
+        self.try_every_after_ack(duration, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every_after_ack<D>(&mut self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let duration = duration.into_duration()?;
+        self.check_recurring_interval(duration)?;
+        let id = self.next_task_id();
+        let task = Task::new(id, duration, self.time_source.now(), TaskBond::FixedDelay, token)?;
+        self.schedule(task);
+        Ok(id)
+    }
+
+    // Schedule next run of fired fixed delay task after its interval from now.
+    // Returns false if task is not waiting for acknowledgement.
+    pub fn ack(&mut self, id: TaskId) -> bool {
+        self.try_ack(id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // task keeps waiting for acknowledgement if its next run does not fit in time points
+    pub fn try_ack(&mut self, id: TaskId) -> Result<bool, SchedulerError> {
+        let next = match self.parked.get(&id) {
+            // fixed delay task does not reschedule only when its next run cannot be represented
+            Some(task) => task.clone().reschedule_from(self.time_source.now()).ok_or(SchedulerError::Overflow)?,
+            None => return Ok(false)
+        };
+        self.try_to_time_point(next.schedule())?;
+        self.parked.remove(&id);
+        self.schedule(next);
+        Ok(true)
+    }
+
+    // runs given number of times
+    pub fn every_times<D>(&mut self, duration: D, count: u64, token: Token) -> TaskId where D: IntoDuration {
+        self.try_every_times(duration, count, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every_times<D>(&mut self, duration: D, count: u64, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let duration = duration.into_duration()?;
+        if count == 0 {
+            return Err(SchedulerError::InvalidCount);
+        }
+        self.check_recurring_interval(duration)?;
+        let id = self.next_task_id();
+        let task = Task::new(id, duration, self.time_source.now(), TaskBond::Repeat(count), token)?;
+        self.schedule(task);
+        Ok(id)
+    }
+
+    // Runs until given time of the time source. Returns None if the first run would be after it as
+    // such task is not scheduled at all.
+    pub fn every_until<D, T>(&mut self, duration: D, deadline: T, token: Token) -> Option<TaskId> where D: IntoDuration, T: IntoDuration {
+        self.try_every_until(duration, deadline, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every_until<D, T>(&mut self, duration: D, deadline: T, token: Token) -> Result<Option<TaskId>, SchedulerError> where D: IntoDuration, T: IntoDuration {
+        let duration = duration.into_duration()?;
+        let deadline = deadline.into_duration()?;
+        let now = self.time_source.now();
+        match now.checked_add(duration) {
+            Some(first_run) if first_run <= deadline => (),
+            _ => return Ok(None)
+        }
+        self.check_recurring_interval(duration)?;
+        let id = self.next_task_id();
+        let task = Task::new(id, duration, now, TaskBond::Until(deadline), token)?;
+        self.schedule(task);
+        Ok(Some(id))
+    }
+
+    // runs for given time from now; see every_until
+    pub fn every_for<D, P>(&mut self, duration: D, period: P, token: Token) -> Option<TaskId> where D: IntoDuration, P: IntoDuration {
+        self.try_every_for(duration, period, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every_for<D, P>(&mut self, duration: D, period: P, token: Token) -> Result<Option<TaskId>, SchedulerError> where D: IntoDuration, P: IntoDuration {
+        let deadline = self.time_source.now().checked_add(period.into_duration()?).ok_or(SchedulerError::Overflow)?;
+        self.try_every_until(duration, deadline, token)
+    }
+
+    // runs with growing delays until cancelled
+    pub fn backoff(&mut self, backoff: Backoff, token: Token) -> TaskId {
+        self.try_backoff(backoff, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_backoff(&mut self, backoff: Backoff, token: Token) -> Result<TaskId, SchedulerError> {
+        backoff.check().map_err(SchedulerError::InvalidBackoff)?;
+        if backoff.initial == Duration::new(0, 0) {
+            return Err(SchedulerError::InvalidInterval);
+        }
+        // delays never grow past max
+        self.check_interval(backoff.max)?;
+        let id = self.next_task_id();
+        let mut rng = self.rng.fork();
+        let interval = backoff.jittered(backoff.initial, &mut rng);
+        let initial = backoff.initial;
+        let bond = TaskBond::Backoff(backoff, initial, rng);
+        let task = Task::new(id, interval, self.time_source.now(), bond, token)?;
+        self.schedule(task);
+        Ok(id)
+    }
+
+    // Schedule next run of backoff task after its initial delay from now and grow the delay from there;
+    // for paused task the delay counts from when it is resumed. Returns false if there is no such task
+    // or it is not a backoff task.
+    pub fn reset(&mut self, id: TaskId) -> bool {
+        self.try_reset(id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_reset(&mut self, id: TaskId) -> Result<bool, SchedulerError> {
+        // backoff tasks never wait for acknowledgement
+        let start = match self.paused.get(&id) {
+            Some(paused) => paused.paused_at,
+            None => self.time_source.now()
+        };
+        let task = match self.task(id) {
+            Some(task) => match reset_backoff(task, start)? {
+                Some(task) => task,
+                None => return Ok(false)
+            },
+            None => return Ok(false)
+        };
+        self.replace_task(task)?;
+        Ok(true)
+    }
+
+    // seed generator used to randomize delays of tasks scheduled from now on; useful for reproducible tests
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = JitterRng::new(seed);
     }
 
     fn next_action(&self) -> SchedulerAction {
         let now = self.time_source.now();
         let current_time_point = self.to_time_point(now);
 
-        match self.tasks.iter().next() {
+        match self.tasks.first_time_point() {
             None => SchedulerAction::None,
-            Some((&time_point, _)) => {
+            Some(time_point) => {
                 match time_point.cmp(&current_time_point) {
                     Ordering::Greater => SchedulerAction::Wait((self.to_duration(time_point)) - now),
-                    Ordering::Less => SchedulerAction::Skip(self.tasks.iter().take_while(|&(&time_point, &_)| time_point < current_time_point).map(|(time_point, _)| time_point.clone()).collect()),
+                    Ordering::Less => SchedulerAction::Skip(self.tasks.time_points_before(current_time_point)),
                     Ordering::Equal => SchedulerAction::Yield(time_point)
                 }
             }
         }
     }
 
-    pub fn next(&mut self) -> Option<Schedule<Token>> {
+    // not an Iterator as None only means there is nothing scheduled right now
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Schedule<Fired<Token>>> {
+        match self.time_source.clock_jump() {
+            Some(ClockJump::Reanchor(offset)) => self.reanchor(offset),
+            Some(ClockJump::Report(offset)) => return Some(Schedule::ClockJump(offset)),
+            None => ()
+        }
+
         match self.next_action() {
             SchedulerAction::None => None,
             SchedulerAction::Wait(duration) => {
@@ -112,15 +408,20 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
             SchedulerAction::Skip(time_points) => {
                 let mut overrun = Vec::new();
 
-                overrun.extend(self.consume(time_points));
+                overrun.extend(self.consume(time_points, true));
                 // collect all reschedules of consumed tasks if they end up overrun already
                 while let SchedulerAction::Skip(time_points) = self.next_action() {
-                    overrun.extend(self.consume(time_points));
+                    overrun.extend(self.consume(time_points, true));
+                }
+
+                if overrun.is_empty() {
+                    // all overrun tasks were skipped
+                    return self.next();
                 }
                 Some(Schedule::Overrun(overrun))
             },
             SchedulerAction::Yield(time_point) => {
-                Some(Schedule::Current(self.consume(vec![time_point])))
+                Some(Schedule::Current(self.consume(vec![time_point], false)))
             }
         }
     }
@@ -128,57 +429,351 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
     pub fn next_in(&self) -> Duration {
         match self.next_action() {
             SchedulerAction::Wait(duration) => duration,
-            _ => Duration::zero()
+            _ => Duration::new(0, 0)
         }
     }
 
-    pub fn cancel(&mut self, token: &Token) where Token: PartialEq<Token> {
-        let mut empty_time_points = vec![];
+    // scheduled, paused or waiting for acknowledgement task
+    pub fn task(&self, id: TaskId) -> Option<&Task<Token>> {
+        self.tasks.get(id)
+            .or_else(|| self.parked.get(&id))
+            .or_else(|| self.paused.get(&id).map(|paused| &paused.task))
+    }
 
-        for (ref time_point, ref mut tasks) in self.tasks.iter_mut() {
-            tasks.retain(|task| task.token != *token);
-            if tasks.is_empty() {
-                empty_time_points.push(*time_point.clone());
+    // Randomize when runs of the task are due using generator seeded by the scheduler; PerRun jitter
+    // replaces earlier one. Splay of task waiting for acknowledgement has no effect as its next run
+    // counts from the acknowledgement. Returns false if there is no such task.
+    pub fn set_jitter(&mut self, id: TaskId, jitter: Jitter) -> bool {
+        self.try_set_jitter(id, jitter).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_set_jitter(&mut self, id: TaskId, jitter: Jitter) -> Result<bool, SchedulerError> {
+        let task = match self.task(id) {
+            Some(task) => task.clone(),
+            None => return Ok(false)
+        };
+        let max = match jitter {
+            Jitter::Splay(max) | Jitter::PerRun(max) => max
+        };
+        // latest jittered run has to fit in time points
+        self.try_to_time_point(task.schedule().checked_add(max).ok_or(SchedulerError::Overflow)?)?;
+
+        let task = task.with_jitter(jitter, self.rng.fork())?;
+        self.replace_task(task)?;
+        Ok(true)
+    }
+
+    // Put changed copy of scheduled, paused or waiting for acknowledgement task in place of the task;
+    // scheduled task is left as it is if its changed run does not fit in time points
+    fn replace_task(&mut self, task: Task<Token>) -> Result<(), SchedulerError> {
+        let id = task.id;
+        if self.tasks.get(id).is_some() {
+            self.try_to_time_point(task.schedule())?;
+            self.tasks.remove(id);
+            self.schedule(task);
+        } else if let Some(parked) = self.parked.get_mut(&id) {
+            *parked = task;
+        } else if let Some(paused) = self.paused.get_mut(&id) {
+            paused.task = task;
+        }
+        Ok(())
+    }
+
+    // scheduled, paused or waiting for acknowledgement task; must not be used to change when the task is due
+    fn task_mut(&mut self, id: TaskId) -> Option<&mut Task<Token>> {
+        match self.tasks.get_mut(id) {
+            Some(task) => Some(task),
+            None => match self.parked.get_mut(&id) {
+                Some(task) => Some(task),
+                None => self.paused.get_mut(&id).map(|paused| &mut paused.task)
             }
         }
+    }
 
-        for time_point in empty_time_points {
-            self.tasks.remove(&time_point).unwrap();
+    // returns false if there is no such task
+    pub fn set_overrun_policy(&mut self, id: TaskId, policy: OverrunPolicy) -> bool {
+        match self.task_mut(id) {
+            Some(task) => {
+                task.overrun_policy = policy;
+                true
+            },
+            None => false
         }
     }
 
-    fn consume(&mut self, time_points: Vec<PointInTime>) -> Vec<Token> {
-        let mut tasks: Vec<Task<Token>> = time_points.iter().flat_map(|time_point|
-                self.tasks.remove(&time_point).unwrap()
+    // Order of the task among tasks fired in the same batch; higher priority comes first and
+    // default is 0. Returns false if there is no such task.
+    pub fn set_priority(&mut self, id: TaskId, priority: i32) -> bool {
+        match self.task_mut(id) {
+            Some(task) => {
+                task.priority = priority;
+                true
+            },
+            None => false
+        }
+    }
+
+    // Change interval between runs of the task keeping its identity; if its next run ends up overdue
+    // it is handled by its overrun policy. Returns false if task is not scheduled or its runs are not
+    // due at fixed interval (cron, backoff and at_time tasks).
+    pub fn set_interval<D>(&mut self, id: TaskId, interval: D, change: IntervalChange) -> bool where D: IntoDuration {
+        self.try_set_interval(id, interval, change).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_set_interval<D>(&mut self, id: TaskId, interval: D, change: IntervalChange) -> Result<bool, SchedulerError> where D: IntoDuration {
+        let interval = interval.into_duration()?;
+        let task = match self.task(id) {
+            Some(&Task { bond: TaskBond::Cron(..), .. }) | Some(&Task { bond: TaskBond::Backoff(..), .. }) | Some(&Task { bond: TaskBond::At(_), .. }) | None => return Ok(false),
+            Some(task) => task.clone()
+        };
+        match task.bond {
+            TaskBond::OneOff => self.check_interval(interval)?,
+            _ => self.check_recurring_interval(interval)?
+        }
+
+        // interval of fixed delay task waiting for acknowledgement applies from the acknowledgement
+        self.replace_task(task.with_interval(interval, change)?)?;
+        Ok(true)
+    }
+
+    pub fn cancel_task(&mut self, id: TaskId) -> Option<Token> {
+        let task = self.tasks.remove(id)
+            .or_else(|| self.parked.remove(&id))
+            .or_else(|| self.paused.remove(&id).map(|paused| paused.task));
+        self.untag(id);
+        task.map(|task| task.token)
+    }
+
+    pub fn cancel(&mut self, token: &Token) where Token: PartialEq<Token> {
+        let mut cancelled = Vec::new();
+        {
+            let mut keep = |task: &Task<Token>| {
+                if task.token == *token {
+                    cancelled.push(task.id);
+                    return false;
+                }
+                true
+            };
+            self.tasks.retain(|task| keep(task));
+            self.parked.retain(|_, task| keep(task));
+            self.paused.retain(|_, paused| keep(&paused.task));
+        }
+        for id in cancelled {
+            self.untag(id);
+        }
+    }
+
+    // Move wall clock anchored (cron and at_time) tasks so they keep their wall clock schedule after time source epoch
+    // moved by offset; paused tasks are moved as well. Done on ClockJump::Reanchor; call it with offset of reported
+    // Schedule::ClockJump to do the same after handling the jump.
+    pub fn reanchor(&mut self, offset: ClockOffset) {
+        for task in self.tasks.drain() {
+            let task = reanchor_task(task, offset);
+            self.schedule(task);
+        }
+        self.parked = self.parked.drain().map(|(id, task)| (id, reanchor_task(task, offset))).collect();
+        self.paused = self.paused.drain().map(|(id, paused)| (id, PausedTask {
+            task: reanchor_task(paused.task, offset),
+            .. paused
+        })).collect();
+    }
+
+    fn consume(&mut self, time_points: Vec<PointInTime>, overrun: bool) -> Vec<Fired<Token>> {
+        let mut tasks: Vec<Task<Token>> = time_points.into_iter().flat_map(|time_point|
+                self.tasks.take(time_point)
             ).collect();
 
-        tasks.sort_by(|a, b| a.run_offset.cmp(&b.run_offset));
-        let tokens = tasks.iter().map(|ref task| task.token.clone()).collect();
+        tasks.sort_by_key(fire_order);
+        let now = self.time_source.now();
+        let mut fired = Vec::with_capacity(tasks.len());
 
         for task in tasks {
-            match task.bond {
-                TaskBond::Perpetual => self.schedule(task.next()),
-                TaskBond::OneOff => ()
+            let id = task.id;
+            if let TaskBond::FixedDelay = task.bond {
+                // overrun or not it runs once and waits for acknowledgement
+                fired.push(Fired::new(&task, now, 1, false));
+                self.parked.insert(task.id, task);
+                continue;
+            }
+
+            let next = if overrun {
+                self.reschedule_overrun(task, now, &mut fired)
+            } else {
+                let next = self.schedulable(task.clone().reschedule());
+                fired.push(Fired::new(&task, now, 1, next.is_none()));
+                next
             };
+
+            match next {
+                Some(task) => self.schedule(task),
+                None => self.untag(id)
+            }
+        }
+        fired
+    }
+
+    // apply task overrun policy; reported runs are pushed to fired
+    fn reschedule_overrun(&self, task: Task<Token>, now: Duration, fired: &mut Vec<Fired<Token>>) -> Option<Task<Token>> {
+        if let OverrunPolicy::CatchUp = task.overrun_policy {
+            let next = self.schedulable(task.clone().reschedule());
+            fired.push(Fired::new(&task, now, 1, next.is_none()));
+            return next;
+        }
+
+        let (runs, next) = self.runs_before(&task, self.to_duration(self.to_time_point(now)));
+
+        let next = match next {
+            Some(next) => next,
+            None => {
+                // last run of the task is always reported
+                fired.push(Fired::new(&task, now, runs, true));
+                return None;
+            }
+        };
+
+        match task.overrun_policy {
+            OverrunPolicy::Skip => Some(next),
+            OverrunPolicy::Coalesce => {
+                fired.push(Fired::new(&task, now, runs, false));
+                Some(next)
+            },
+            OverrunPolicy::Delay => {
+                // next run counts from now but runs merged into reported one still count towards its bond
+                let next = self.schedulable(Task {
+                    iteration: task.iteration + runs - 1,
+                    .. task.clone()
+                }.reschedule_from(now));
+                fired.push(Fired::new(&task, now, runs, next.is_none()));
+                next
+            },
+            OverrunPolicy::CatchUp => unreachable!()
         }
-        tokens
+    }
+
+    // number of runs of overdue task that are due before given time and its first run that is not
+    fn runs_before(&self, task: &Task<Token>, time: Duration) -> (u64, Option<Task<Token>>) {
+        let mut runs = 1;
+        let mut next = self.schedulable(task.clone().reschedule());
+        while let Some(next_task) = next.take() {
+            if next_task.schedule() >= time {
+                return (runs, Some(next_task));
+            }
+            let (skipped, next_task) = skip_runs_before(next_task, time);
+            runs += 1 + skipped;
+            next = self.schedulable(next_task.reschedule());
+        }
+        (runs, None)
+    }
+
+    // task with run that does not fit in time points does not run again
+    fn schedulable(&self, task: Option<Task<Token>>) -> Option<Task<Token>> {
+        task.filter(|task| self.try_to_time_point(task.schedule()).is_ok())
     }
 
     fn to_time_point(&self, duration: Duration) -> PointInTime {
-        // nanoseconds gives 15250 weeks or 299 years of duration max... should do?
-        let interval = self.time_point_interval.num_nanoseconds().expect("interval too large");
-        let duration = duration.num_nanoseconds().expect("duration too large");
-        assert!(duration >= 0);
+        self.try_to_time_point(duration).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_to_time_point(&self, duration: Duration) -> Result<PointInTime, SchedulerError> {
+        // nanoseconds gives 30500 weeks or 584 years of duration max... should do?
+        if duration.as_nanos() > u128::from(u64::MAX) {
+            return Err(SchedulerError::Overflow);
+        }
 
-        (duration / interval) as PointInTime
+        Ok((duration.as_nanos() / self.time_point_interval.as_nanos()) as PointInTime)
     }
 
     fn to_duration(&self, time_point: PointInTime) -> Duration {
-        Duration::nanoseconds(self.time_point_interval.num_nanoseconds().expect("time point interval too large") * time_point as i64)
+        self.try_to_duration(time_point).unwrap_or_else(|err| panic!("{}", err))
     }
+
+    fn try_to_duration(&self, time_point: PointInTime) -> Result<Duration, SchedulerError> {
+        // time point interval was checked to fit in nanoseconds on construction
+        (self.time_point_interval.as_nanos() as u64).checked_mul(time_point)
+            .map(Duration::from_nanos)
+            .ok_or(SchedulerError::Overflow)
+    }
+}
+
+// Order of tasks in a batch of fired tasks: higher priority first; then by time the run was scheduled from
+// (previous run of the task or when it was added) with task id breaking ties so that order does not depend
+// on how time points were collected
+fn fire_order<Token>(task: &Task<Token>) -> (cmp::Reverse<i32>, Option<Duration>, TaskId) where Token: Clone {
+    (cmp::Reverse(task.priority), task.due.checked_sub(task.interval), task.id)
+}
+
+// Task moved so that it keeps its wall clock schedule after time source epoch moved by offset. Schedule cannot go
+// before time source zero or past the last time point; such task is just overdue or stays at the last time point.
+fn reanchor_task<Token>(task: Task<Token>, offset: ClockOffset) -> Task<Token> where Token: Clone {
+    let due = match offset {
+        ClockOffset::Forward(offset) => task.due.checked_sub(offset).unwrap_or_default(),
+        ClockOffset::Backward(offset) => {
+            let jitter = task.run_jitter.as_ref().map(|run_jitter| run_jitter.offset).unwrap_or_default();
+            let last = Duration::from_nanos(u64::MAX).checked_sub(jitter).unwrap_or_default();
+            match task.due.checked_add(offset) {
+                Some(due) => cmp::min(due, last),
+                None => last
+            }
+        }
+    };
+    match task.bond {
+        TaskBond::Cron(schedule, epoch) => Task {
+            due,
+            // epoch of the time source was moved by the same offset so it is always representable
+            bond: TaskBond::Cron(schedule, offset.checked_apply(epoch).unwrap_or(epoch)),
+            .. task
+        },
+        TaskBond::At(time) => Task {
+            due,
+            bond: TaskBond::At(time),
+            .. task
+        },
+        bond => Task { bond, .. task }
+    }
+}
+
+// backoff task with its next run after initial delay from given time; None if it is not a backoff task
+fn reset_backoff<Token>(task: &Task<Token>, start: Duration) -> Result<Option<Task<Token>>, SchedulerError> where Token: Clone {
+    match task.bond {
+        TaskBond::Backoff(ref backoff, _, ref rng) => {
+            let mut rng = rng.clone();
+            let interval = backoff.jittered(backoff.initial, &mut rng);
+            Ok(Some(Task {
+                due: start.checked_add(interval).ok_or(SchedulerError::Overflow)?,
+                interval,
+                bond: TaskBond::Backoff(backoff.clone(), backoff.initial, rng),
+                .. task.clone()
+            }))
+        },
+        _ => Ok(None)
+    }
+}
+
+// Overdue task moved by as many runs due before given time as its bond allows, with number of the runs; only
+// tasks with fixed interval and no run jitter are moved so that runs do not have to be counted one by one
+fn skip_runs_before<Token>(task: Task<Token>, time: Duration) -> (u64, Task<Token>) where Token: Clone {
+    let interval = task.interval.as_nanos();
+    if task.run_jitter.is_some() || interval == 0 || task.due >= time {
+        return (0, task);
+    }
+    // runs after this one that are due before given time
+    let before = ((time - task.due).as_nanos() - 1) / interval;
+    let left = match task.bond {
+        TaskBond::Perpetual => before,
+        TaskBond::Repeat(count) => u128::from(count.saturating_sub(task.iteration + 1)),
+        TaskBond::Until(deadline) => deadline.checked_sub(task.due).map(|left| left.as_nanos() / interval).unwrap_or_default(),
+        _ => 0
+    };
+    // less than time which fits in nanoseconds
+    let skipped = cmp::min(before, left) as u64;
+    (skipped, Task {
+        due: task.due + Duration::from_nanos((interval * u128::from(skipped)) as u64),
+        iteration: task.iteration + skipped,
+        .. task
+    })
 }
 
-impl<Token, TS> FastForward for Scheduler<Token, TS> where TS: TimeSource + FastForward, Token: Clone {
+impl<Token, TS, Q> FastForward for Scheduler<Token, TS, Q> where TS: TimeSource + FastForward, Token: Clone, Q: TaskQueue<Token> {
     fn fast_forward(&mut self, duration: Duration) {
         self.time_source.fast_forward(duration);
     }
@@ -187,89 +782,93 @@ impl<Token, TS> FastForward for Scheduler<Token, TS> where TS: TimeSource + Fast
 #[cfg(test)]
 mod test {
     use super::*;
-    use time_source::*;
     use test_helpers::*;
-    use time::Duration;
+    use timing_wheel_task_queue::*;
+    use std::time::Duration;
+
+    fn weeks(weeks: u64) -> Duration {
+        Duration::from_secs(weeks * 7 * 24 * 60 * 60)
+    }
 
     #[test]
     fn to_time_point() {
-        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::seconds(1));
-        assert_eq!(scheduler.to_time_point(Duration::seconds(0)), 0);
-        assert_eq!(scheduler.to_time_point(Duration::seconds(1)), 1);
-        assert_eq!(scheduler.to_time_point(Duration::seconds(2)), 2);
-        assert_eq!(scheduler.to_time_point(Duration::milliseconds(2000)), 2);
-        assert_eq!(scheduler.to_time_point(Duration::milliseconds(100)), 0);
-        assert_eq!(scheduler.to_time_point(Duration::milliseconds(1100)), 1);
-        assert_eq!(scheduler.to_time_point(Duration::milliseconds(1500)), 1);
-        assert_eq!(scheduler.to_time_point(Duration::milliseconds(1800)), 1);
-        assert_eq!(scheduler.to_time_point(Duration::milliseconds(2800)), 2);
+        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::from_secs(1));
+        assert_eq!(scheduler.to_time_point(Duration::from_secs(0)), 0);
+        assert_eq!(scheduler.to_time_point(Duration::from_secs(1)), 1);
+        assert_eq!(scheduler.to_time_point(Duration::from_secs(2)), 2);
+        assert_eq!(scheduler.to_time_point(Duration::from_millis(2000)), 2);
+        assert_eq!(scheduler.to_time_point(Duration::from_millis(100)), 0);
+        assert_eq!(scheduler.to_time_point(Duration::from_millis(1100)), 1);
+        assert_eq!(scheduler.to_time_point(Duration::from_millis(1500)), 1);
+        assert_eq!(scheduler.to_time_point(Duration::from_millis(1800)), 1);
+        assert_eq!(scheduler.to_time_point(Duration::from_millis(2800)), 2);
     }
 
     #[test]
     fn to_time_point_limits() {
-        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::nanoseconds(1));
-        assert_eq!(scheduler.to_time_point(Duration::seconds(0)), 0);
+        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::from_nanos(1));
+        assert_eq!(scheduler.to_time_point(Duration::from_secs(0)), 0);
 
-        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::weeks(15250) / 2);
-        assert_eq!(scheduler.to_time_point(Duration::weeks(0)), 0);
-        assert_eq!(scheduler.to_time_point(Duration::weeks(15250) / 2), 1);
-        assert_eq!(scheduler.to_time_point(Duration::weeks(15250)), 2);
+        let scheduler: Scheduler<(), _> = Scheduler::new(weeks(30500) / 2);
+        assert_eq!(scheduler.to_time_point(weeks(0)), 0);
+        assert_eq!(scheduler.to_time_point(weeks(30500) / 2), 1);
+        assert_eq!(scheduler.to_time_point(weeks(30500)), 2);
     }
 
     #[test]
     fn to_duration() {
-        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::seconds(1));
-        assert_eq!(scheduler.to_duration(0), Duration::seconds(0));
-        assert_eq!(scheduler.to_duration(1), Duration::seconds(1));
-        assert_eq!(scheduler.to_duration(2), Duration::seconds(2));
+        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::from_secs(1));
+        assert_eq!(scheduler.to_duration(0), Duration::from_secs(0));
+        assert_eq!(scheduler.to_duration(1), Duration::from_secs(1));
+        assert_eq!(scheduler.to_duration(2), Duration::from_secs(2));
     }
 
     #[test]
     fn to_duration_limits() {
-        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::nanoseconds(1));
-        assert_eq!(scheduler.to_duration(0), Duration::nanoseconds(0));
-        assert_eq!(scheduler.to_duration(1), Duration::nanoseconds(1));
-
-        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::weeks(15250) / 2);
-        assert_eq!(scheduler.to_duration(0), Duration::seconds(0));
-        assert_eq!(scheduler.to_duration(1), Duration::weeks(15250) / 2);
-        assert_eq!(scheduler.to_duration(2), Duration::weeks(15250));
+        let scheduler: Scheduler<(), _> = Scheduler::new(Duration::from_nanos(1));
+        assert_eq!(scheduler.to_duration(0), Duration::from_nanos(0));
+        assert_eq!(scheduler.to_duration(1), Duration::from_nanos(1));
+
+        let scheduler: Scheduler<(), _> = Scheduler::new(weeks(30500) / 2);
+        assert_eq!(scheduler.to_duration(0), Duration::from_secs(0));
+        assert_eq!(scheduler.to_duration(1), weeks(30500) / 2);
+        assert_eq!(scheduler.to_duration(2), weeks(30500));
     }
 
     #[test]
     fn empty() {
-        let mut scheduler: Scheduler<(), _> = Scheduler::new(Duration::seconds(1));
-        assert_eq!(scheduler.next(), None);
-        assert_eq!(scheduler.next(), None);
+        let mut scheduler: Scheduler<(), _> = Scheduler::new(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), None);
+        assert_eq!(scheduler.next().tokens(), None);
     }
 
     #[test]
     fn after() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![0])));
+        scheduler.after(Duration::from_secs(0), 0);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![0])));
 
-        scheduler.after(Duration::seconds(1), 1);
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::seconds(1))));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::seconds(1))));
+        scheduler.after(Duration::from_secs(1), 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(1))));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(1))));
 
-        scheduler.fast_forward(Duration::milliseconds(100));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::milliseconds(900))));
-        scheduler.fast_forward(Duration::milliseconds(900));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![1])));
-        assert_eq!(scheduler.next(), None);
+        scheduler.fast_forward(Duration::from_millis(100));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_millis(900))));
+        scheduler.fast_forward(Duration::from_millis(900));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), None);
     }
 
     #[test]
     fn after_opaque_token() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), Zero);
+        scheduler.after(Duration::from_secs(0), Zero);
         match scheduler.next().unwrap() {
             Schedule::Current(tokens) => {
                 assert_eq!(tokens.len(), 1);
-                tokens.first().unwrap().expect(Zero)
+                tokens.first().unwrap().token.expect(Zero)
             }
             _ => panic!("expected Schedule::Current")
         }
@@ -277,112 +876,835 @@ mod test {
 
     #[test]
     fn every() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
-
-        scheduler.every(Duration::seconds(1), 1);
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::seconds(1))));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::seconds(1))));
-
-        scheduler.fast_forward(Duration::milliseconds(100));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::milliseconds(900))));
-        scheduler.fast_forward(Duration::milliseconds(900));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![1])));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::seconds(1))));
-
-        scheduler.fast_forward(Duration::milliseconds(600));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::milliseconds(400))));
-        scheduler.fast_forward(Duration::milliseconds(500));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![1])));
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::milliseconds(900))));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.every(Duration::from_secs(1), 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(1))));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(1))));
+
+        scheduler.fast_forward(Duration::from_millis(100));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_millis(900))));
+        scheduler.fast_forward(Duration::from_millis(900));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(1))));
+
+        scheduler.fast_forward(Duration::from_millis(600));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_millis(400))));
+        scheduler.fast_forward(Duration::from_millis(500));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_millis(900))));
     }
 
     #[test]
     fn every_with_overrun() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.every(Duration::seconds(1), 1);
-        scheduler.fast_forward(Duration::seconds(4));
-        assert_eq!(scheduler.next(), Some(Schedule::Overrun(vec![1, 1, 1])));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![1])));
+        scheduler.every(Duration::from_secs(1), 1);
+        scheduler.fast_forward(Duration::from_secs(4));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![1, 1, 1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
     }
 
     #[test]
     fn limits() {
-        let mut scheduler = Scheduler::with_time_source(Duration::nanoseconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_nanos(1), MockTimeSource::new());
+
+        scheduler.after(Duration::from_nanos(1), 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_nanos(1))));
+
+        scheduler.fast_forward(Duration::from_nanos(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+
+        scheduler.after(weeks(30500), 2);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(weeks(30500))));
 
-        scheduler.after(Duration::nanoseconds(1), 1);
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::nanoseconds(1))));
+        scheduler.fast_forward(weeks(30500));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
 
-        scheduler.fast_forward(Duration::nanoseconds(1));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![1])));
+        let mut scheduler = Scheduler::with_time_source(weeks(30500) / 2, MockTimeSource::new());
 
-        scheduler.after(Duration::weeks(15250), 2);
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::weeks(15250))));
+        scheduler.after(weeks(30500) / 2, 1);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(weeks(30500) / 2)));
 
-        scheduler.fast_forward(Duration::weeks(15250));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![2])));
+        scheduler.fast_forward(weeks(30500) / 2);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+    }
 
-        let mut scheduler = Scheduler::with_time_source(Duration::weeks(15250) / 2, MockTimeSource::new());
+    #[test]
+    fn try_limits() {
+        assert_eq!(Scheduler::<(), _>::try_with_time_source(Duration::new(0, 0), MockTimeSource::new()).err(),
+            Some(SchedulerError::InvalidTimePointInterval(Duration::new(0, 0))));
+        assert_eq!(Scheduler::<(), _>::try_with_time_source(weeks(30501), MockTimeSource::new()).err(),
+            Some(SchedulerError::InvalidTimePointInterval(weeks(30501))));
+        let mut queue = BTreeTaskQueue::new();
+        queue.insert(0, Task::new(TaskId(0), Duration::new(0, 0), Duration::new(0, 0), TaskBond::OneOff, ()).unwrap());
+        assert_eq!(Scheduler::try_with_task_queue(Duration::from_secs(1), MockTimeSource::new(), queue).err(),
+            Some(SchedulerError::TaskQueueNotEmpty));
+
+        let mut scheduler = Scheduler::try_with_time_source(Duration::from_secs(1), MockTimeSource::new()).unwrap();
+        assert_eq!(scheduler.try_every(weeks(30501), 2), Err(SchedulerError::Overflow));
+        scheduler.fast_forward(weeks(30500));
+        assert_eq!(scheduler.try_every(weeks(1), 3), Err(SchedulerError::Overflow));
+        assert!(scheduler.is_empty());
+
+        assert_eq!(scheduler.try_after(Duration::from_secs(1), 4), Ok(TaskId(0)));
+        assert_eq!(scheduler.try_to_time_point(Duration::from_secs(u64::MAX)), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_to_duration(u64::MAX), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_to_duration(i64::MAX as u64), Err(SchedulerError::Overflow));
+    }
+
+    #[test]
+    fn try_variants() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        // so that deadline of every_for overflows
+        scheduler.fast_forward(Duration::from_secs(1));
+
+        assert_eq!(scheduler.try_every_after_ack(weeks(30501), 1), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_every_times(Duration::from_secs(1), 0, 1), Err(SchedulerError::InvalidCount));
+        assert_eq!(scheduler.try_every_times(weeks(30501), 3, 1), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_every_until(weeks(30501), weeks(30502), 1), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_every_until(Duration::from_secs(2), Duration::from_secs(1), 1), Ok(None));
+        assert_eq!(scheduler.try_every_for(Duration::from_secs(1), Duration::from_secs(u64::MAX), 1), Err(SchedulerError::Overflow));
+        let backoff = Backoff { initial: Duration::from_secs(2), multiplier: 2.0, max: Duration::from_secs(1), jitter: 0.0 };
+        assert_eq!(scheduler.try_backoff(backoff, 1), Err(SchedulerError::InvalidBackoff(BackoffError::MaxLessThanInitial)));
+        assert_eq!(scheduler.try_backoff(Backoff::new(Duration::from_secs(1), 2.0, weeks(30501)).unwrap(), 1), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_at(weeks(30501), 1), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_after_tagged(weeks(30501), 1, &["tag"]), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_every_tagged(weeks(30501), 1, &["tag"]), Err(SchedulerError::Overflow));
+        assert!(scheduler.is_empty());
+        assert!(scheduler.tagged("tag").is_empty());
+
+        let id = scheduler.try_every_times(Duration::from_secs(1), 3, 1).unwrap();
+        assert_eq!(scheduler.try_set_interval(id, weeks(30501), IntervalChange::KeepNextRun), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_set_interval(TaskId(42), weeks(30501), IntervalChange::KeepNextRun), Ok(false));
+        assert_eq!(scheduler.try_set_interval(id, Duration::from_secs(2), IntervalChange::KeepNextRun), Ok(true));
+        assert_eq!(scheduler.task(id).unwrap().interval, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn reschedule_overflow() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let ack = scheduler.every_after_ack(weeks(1), 1);
+        scheduler.every(weeks(20000), 2);
+
+        scheduler.fast_forward(weeks(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+
+        // next run of 2 would be past the last time point so it ends with its first run
+        scheduler.fast_forward(weeks(19999));
+        match scheduler.next() {
+            Some(Schedule::Current(fired)) => {
+                assert_eq!(fired.clone().tokens(), vec![2]);
+                assert!(fired[0].last);
+            },
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+
+        let backoff = scheduler.backoff(Backoff::new(weeks(1), 2.0, weeks(1)).unwrap(), 3);
+        scheduler.fast_forward(weeks(10500));
+        assert_eq!(scheduler.try_ack(ack), Err(SchedulerError::Overflow));
+        assert!(scheduler.task(ack).is_some());
+        assert_eq!(scheduler.try_reset(backoff), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_reset(ack), Ok(false));
+        assert_eq!(scheduler.len(), 2);
+    }
+
+    #[test]
+    fn std_duration() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(100), MockTimeSource::new());
 
-        scheduler.after(Duration::weeks(15250) / 2, 1);
-        assert_eq!(scheduler.next(), Some(Schedule::NextIn(Duration::weeks(15250) / 2)));
+        scheduler.after(Duration::from_millis(1500), 1);
+        scheduler.every(Duration::from_secs(1), 2);
+        assert_eq!(scheduler.try_every(Duration::from_secs(u64::MAX), 3), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
 
-        scheduler.fast_forward(Duration::weeks(15250) / 2);
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![1])));
+        scheduler.fast_forward(Duration::from_millis(1500));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
     }
 
     #[test]
     fn next_in() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        assert_eq!(scheduler.next_in(), Duration::zero());
+        scheduler.after(Duration::from_secs(0), 0);
+        assert_eq!(scheduler.next_in(), Duration::new(0, 0));
         scheduler.next();
 
-        scheduler.fast_forward(Duration::seconds(1));
-        scheduler.after(Duration::seconds(1), 1);
-        assert_eq!(scheduler.next_in(), Duration::seconds(1));
+        scheduler.fast_forward(Duration::from_secs(1));
+        scheduler.after(Duration::from_secs(1), 1);
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
         scheduler.next();
 
-        scheduler.fast_forward(Duration::seconds(2));
-        assert_eq!(scheduler.next_in(), Duration::zero());
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next_in(), Duration::new(0, 0));
     }
 
     #[test]
     fn cancel_whole_time_point() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
-        scheduler.after(Duration::seconds(3), 4);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
+        scheduler.after(Duration::from_secs(3), 4);
 
         scheduler.cancel(&1);
         scheduler.cancel(&2);
 
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![0])));
-        scheduler.fast_forward(Duration::seconds(3));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![4])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![0])));
+        scheduler.fast_forward(Duration::from_secs(3));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![4])));
     }
 
     #[test]
     fn cancel_single_token() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(1), 2);
-        scheduler.after(Duration::seconds(1), 3);
-        scheduler.after(Duration::seconds(2), 4);
-        scheduler.after(Duration::seconds(2), 5);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(1), 2);
+        scheduler.after(Duration::from_secs(1), 3);
+        scheduler.after(Duration::from_secs(2), 4);
+        scheduler.after(Duration::from_secs(2), 5);
 
         scheduler.cancel(&1);
         scheduler.cancel(&4);
 
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![0])));
-        scheduler.fast_forward(Duration::seconds(1));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![2, 3])));
-        scheduler.fast_forward(Duration::seconds(1));
-        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![5])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![0])));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2, 3])));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![5])));
+    }
+
+    #[test]
+    fn cancel_task_with_equal_tokens() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let first = scheduler.after(Duration::from_secs(1), 1);
+        let second = scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(1), 2);
+
+        assert_eq!(scheduler.cancel_task(first), Some(1));
+        assert_eq!(scheduler.cancel_task(first), None);
+
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1, 2])));
+        assert_eq!(scheduler.cancel_task(second), None);
+    }
+
+    #[test]
+    fn cancel_task_perpetual() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_secs(1), 1);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+
+        assert_eq!(scheduler.cancel_task(id), Some(1));
+        assert_eq!(scheduler.next().tokens(), None);
+    }
+
+    #[test]
+    fn task() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let once = scheduler.after(Duration::from_secs(2), 1);
+        let every = scheduler.every(Duration::from_secs(1), 1);
+        assert!(once != every);
+
+        assert_eq!(scheduler.task(once).map(|task| task.schedule()), Some(Duration::from_secs(2)));
+        assert_eq!(scheduler.task(every).map(|task| task.schedule()), Some(Duration::from_secs(1)));
+
+        scheduler.fast_forward(Duration::from_secs(1));
+        scheduler.next();
+        assert_eq!(scheduler.task(every).map(|task| task.schedule()), Some(Duration::from_secs(2)));
+
+        scheduler.cancel(&1);
+        assert!(scheduler.task(once).is_none());
+        assert!(scheduler.task(every).is_none());
+    }
+
+    #[test]
+    fn every_with_overrun_coalesce() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_secs(1), 1);
+        assert!(scheduler.set_overrun_policy(id, OverrunPolicy::Coalesce));
+        scheduler.fast_forward(Duration::from_secs(4));
+        match scheduler.next() {
+            Some(Schedule::Overrun(fired)) => {
+                assert_eq!(fired.len(), 1);
+                assert_eq!(fired[0].token, 1);
+                assert_eq!(fired[0].runs, 3);
+            },
+            schedule => panic!("expected Schedule::Overrun but got {:?}", schedule)
+        }
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn every_with_overrun_skip() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
+        assert!(scheduler.set_overrun_policy(id, OverrunPolicy::Skip));
+
+        scheduler.fast_forward(Duration::from_secs(4));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+
+        scheduler.fast_forward(Duration::from_millis(3500));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_millis(500))));
+    }
+
+    #[test]
+    fn every_with_overrun_delay() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_secs(1), 1);
+        assert!(scheduler.set_overrun_policy(id, OverrunPolicy::Delay));
+        scheduler.fast_forward(Duration::from_millis(4500));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![1])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_millis(500))));
+        assert_eq!(scheduler.task(id).map(|task| task.schedule()), Some(Duration::from_millis(5500)));
+    }
+
+    #[test]
+    fn after_with_overrun_skip() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.after(Duration::from_secs(1), 1);
+        assert!(scheduler.set_overrun_policy(id, OverrunPolicy::Skip));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![1])));
+        assert_eq!(scheduler.next().tokens(), None);
+        assert!(!scheduler.set_overrun_policy(id, OverrunPolicy::Skip));
+    }
+
+    #[test]
+    fn set_overrun_policy_paused_and_parked() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let paused = scheduler.every(Duration::from_secs(1), 1);
+        assert!(scheduler.pause_task(paused));
+        assert!(scheduler.set_overrun_policy(paused, OverrunPolicy::Skip));
+        assert_eq!(scheduler.task(paused).unwrap().overrun_policy, OverrunPolicy::Skip);
+
+        let parked = scheduler.every_after_ack(Duration::from_secs(1), 2);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+        assert!(scheduler.set_overrun_policy(parked, OverrunPolicy::Coalesce));
+        assert_eq!(scheduler.task(parked).unwrap().overrun_policy, OverrunPolicy::Coalesce);
+    }
+
+    #[test]
+    fn priority() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.after(Duration::from_secs(2), 1);
+        let high = scheduler.after(Duration::from_secs(2), 2);
+        scheduler.fast_forward(Duration::from_millis(500));
+        scheduler.after(Duration::from_millis(1500), 3);
+        let low = scheduler.after(Duration::from_millis(1500), 4);
+        assert!(scheduler.set_priority(high, 10));
+        assert!(scheduler.set_priority(low, -1));
+        assert!(!scheduler.set_priority(TaskId(42), 1));
+
+        scheduler.fast_forward(Duration::from_millis(1500));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2, 1, 3, 4])));
+
+        // same order regardless of which time points tasks were taken from
+        scheduler.after(Duration::from_secs(3), 1);
+        let high = scheduler.after(Duration::from_secs(1), 2);
+        let low = scheduler.after(Duration::from_secs(1), 3);
+        scheduler.after(Duration::from_secs(2), 4);
+        scheduler.set_priority(high, 1);
+        scheduler.set_priority(low, -1);
+        scheduler.fast_forward(Duration::from_secs(5));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2, 1, 4, 3])));
+    }
+
+    #[test]
+    fn priority_rescheduled() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.every(Duration::from_secs(3), 1);
+        scheduler.fast_forward(Duration::from_secs(1));
+        scheduler.after(Duration::from_secs(2), 2);
+        scheduler.after(Duration::from_secs(5), 3);
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1, 2])));
+
+        // recurring task was rescheduled from its run that was after the other task was added
+        scheduler.fast_forward(Duration::from_secs(3));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![3, 1])));
+    }
+
+    #[test]
+    fn fired() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_millis(1500), 1);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![Fired {
+            id,
+            token: 1,
+            scheduled: Duration::from_millis(1500),
+            fired: Duration::from_secs(1),
+            interval: Duration::from_millis(1500),
+            lateness: Duration::new(0, 0),
+            iteration: 0,
+            runs: 1,
+            last: false
+        }])));
+
+        scheduler.fast_forward(Duration::from_secs(3));
+        assert_eq!(scheduler.next(), Some(Schedule::Overrun(vec![Fired {
+            id,
+            token: 1,
+            scheduled: Duration::from_secs(3),
+            fired: Duration::from_secs(4),
+            interval: Duration::from_millis(1500),
+            lateness: Duration::from_secs(1),
+            iteration: 1,
+            runs: 1,
+            last: false
+        }])));
+        assert_eq!(scheduler.next(), Some(Schedule::Current(vec![Fired {
+            id,
+            token: 1,
+            scheduled: Duration::from_millis(4500),
+            fired: Duration::from_secs(4),
+            interval: Duration::from_millis(1500),
+            lateness: Duration::new(0, 0),
+            iteration: 2,
+            runs: 1,
+            last: false
+        }])));
+    }
+
+    #[test]
+    fn every_times() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.every_times(Duration::from_secs(1), 2, 1);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().unwrap(), Schedule::Current(vec![Fired {
+            id: TaskId(0),
+            token: 1,
+            scheduled: Duration::from_secs(1),
+            fired: Duration::from_secs(1),
+            interval: Duration::from_secs(1),
+            lateness: Duration::new(0, 0),
+            iteration: 0,
+            runs: 1,
+            last: false
+        }]));
+
+        scheduler.fast_forward(Duration::from_secs(1));
+        match scheduler.next() {
+            Some(Schedule::Current(fired)) => assert!(fired[0].last),
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+        assert_eq!(scheduler.next().tokens(), None);
+    }
+
+    #[test]
+    fn every_times_with_overrun() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every_times(Duration::from_secs(1), 3, 1);
+        assert!(scheduler.set_overrun_policy(id, OverrunPolicy::Coalesce));
+        scheduler.fast_forward(Duration::from_secs(10));
+        match scheduler.next() {
+            Some(Schedule::Overrun(fired)) => {
+                assert_eq!(fired[0].runs, 3);
+                assert!(fired[0].last);
+            },
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+        assert_eq!(scheduler.next().tokens(), None);
+    }
+
+    #[test]
+    fn every_times_with_overrun_merged() {
+        for &policy in &[OverrunPolicy::Delay, OverrunPolicy::Coalesce] {
+            let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+            let id = scheduler.every_times(Duration::from_secs(1), 5, 1);
+            assert!(scheduler.set_overrun_policy(id, policy));
+            scheduler.fast_forward(Duration::from_millis(3500));
+
+            let mut fired = Vec::new();
+            while let Some(schedule) = scheduler.next() {
+                match schedule {
+                    Schedule::Overrun(batch) | Schedule::Current(batch) => fired.extend(batch),
+                    Schedule::NextIn(duration) => scheduler.fast_forward(duration),
+                    Schedule::ClockJump(_) => unreachable!()
+                }
+            }
+
+            // runs at 1 and 2 seconds are merged into one record
+            assert_eq!(fired[0].runs, 2);
+            assert_eq!(fired.iter().map(|fired| fired.runs).sum::<u64>(), 5, "{:?}", policy);
+            assert_eq!(fired.iter().map(|fired| fired.iteration).collect::<Vec<_>>(), vec![0, 2, 3, 4]);
+            assert_eq!(fired.iter().map(|fired| fired.last).collect::<Vec<_>>(), vec![false, false, false, true]);
+        }
+    }
+
+    #[test]
+    fn overrun_many_runs() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        // one run per nanosecond for 1000 weeks
+        let every = scheduler.every(Duration::from_nanos(1), 1);
+        let times = scheduler.every_times(Duration::from_nanos(1), 10, 2);
+        let until = scheduler.every_until(Duration::from_nanos(1), Duration::from_nanos(100), 3).unwrap();
+        for &id in &[every, times, until] {
+            assert!(scheduler.set_overrun_policy(id, OverrunPolicy::Coalesce));
+        }
+        scheduler.fast_forward(weeks(1000));
+
+        match scheduler.next() {
+            Some(Schedule::Overrun(fired)) => {
+                assert_eq!(fired.iter().map(|fired| (fired.token, fired.runs, fired.last)).collect::<Vec<_>>(),
+                    vec![(1, weeks(1000).as_nanos() as u64 - 1, false), (2, 10, true), (3, 100, true)]);
+                assert_eq!(fired[1].iteration, 0);
+            },
+            schedule => panic!("expected Schedule::Overrun but got {:?}", schedule)
+        }
+        assert_eq!(scheduler.task(every).unwrap().schedule(), weeks(1000));
+        assert_eq!(scheduler.task(every).unwrap().iteration, weeks(1000).as_nanos() as u64 - 1);
+    }
+
+    #[test]
+    fn zero_interval() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let zero = Duration::new(0, 0);
+
+        assert_eq!(scheduler.try_every(zero, 1), Err(SchedulerError::InvalidInterval));
+        assert_eq!(scheduler.try_every_after_ack(zero, 1), Err(SchedulerError::InvalidInterval));
+        assert_eq!(scheduler.try_every_times(zero, 2, 1), Err(SchedulerError::InvalidInterval));
+        assert_eq!(scheduler.try_every_until(zero, Duration::from_secs(1), 1), Err(SchedulerError::InvalidInterval));
+        assert_eq!(scheduler.try_backoff(Backoff::new(zero, 2.0, Duration::from_secs(1)).unwrap(), 1), Err(SchedulerError::InvalidInterval));
+        assert!(scheduler.is_empty());
+
+        let every = scheduler.every(Duration::from_secs(1), 1);
+        assert_eq!(scheduler.try_set_interval(every, zero, IntervalChange::KeepNextRun), Err(SchedulerError::InvalidInterval));
+        let once = scheduler.after(Duration::from_secs(1), 2);
+        assert_eq!(scheduler.try_set_interval(once, zero, IntervalChange::FromLastRun), Ok(true));
+    }
+
+    #[test]
+    fn every_until() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        assert!(scheduler.every_for(Duration::from_secs(2), Duration::from_secs(5), 1).is_some());
+        assert!(scheduler.every_until(Duration::from_secs(3), Duration::from_secs(3), 2).is_some());
+        assert_eq!(scheduler.every_until(Duration::from_secs(3), Duration::from_secs(2), 3), None);
+        assert_eq!(scheduler.len(), 2);
+
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().map(|schedule| match schedule {
+            Schedule::Current(fired) => fired.iter().map(|fired| (fired.token, fired.last)).collect(),
+            _ => Vec::new()
+        }), Some(vec![(2, true)]));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().map(|schedule| match schedule {
+            Schedule::Current(fired) => fired.iter().map(|fired| (fired.token, fired.last)).collect(),
+            _ => Vec::new()
+        }), Some(vec![(1, true)]));
+        assert_eq!(scheduler.next().tokens(), None);
+    }
+
+    #[test]
+    fn backoff() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.backoff(Backoff::new(Duration::from_secs(1), 2.0, Duration::from_secs(4)).unwrap(), 1);
+        let mut intervals = Vec::new();
+        for _ in 0..4 {
+            let next_in = scheduler.next_in();
+            scheduler.fast_forward(next_in);
+            match scheduler.next() {
+                Some(Schedule::Current(fired)) => intervals.push(fired[0].interval),
+                schedule => panic!("unexpected schedule: {:?}", schedule)
+            }
+        }
+        assert_eq!(intervals, vec![Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4), Duration::from_secs(4)]);
+
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert!(scheduler.reset(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(2));
+
+        let id = scheduler.every(Duration::from_secs(1), 2);
+        assert!(!scheduler.reset(id));
+        assert!(!scheduler.reset(TaskId(42)));
+    }
+
+    #[test]
+    fn backoff_reset_paused() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.backoff(Backoff::new(Duration::from_secs(1), 2.0, Duration::from_secs(8)).unwrap(), 1);
+        for _ in 0..3 {
+            let next_in = scheduler.next_in();
+            scheduler.fast_forward(next_in);
+            scheduler.next();
+        }
+        assert_eq!(scheduler.next_in(), Duration::from_secs(8));
+
+        assert!(scheduler.pause_task(id));
+        scheduler.fast_forward(Duration::from_secs(5));
+        assert!(scheduler.reset(id));
+        // initial delay counts from resume
+        scheduler.fast_forward(Duration::from_secs(5));
+        assert!(scheduler.resume_task(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(2));
+
+        // same when whole scheduler is paused
+        scheduler.pause();
+        assert!(scheduler.reset(id));
+        scheduler.resume();
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
+
+        let id = scheduler.every_after_ack(Duration::from_secs(1), 2);
+        scheduler.fast_forward(Duration::from_secs(1));
+        scheduler.next();
+        assert!(!scheduler.reset(id));
+    }
+
+    #[test]
+    fn backoff_jitter_seed() {
+        let intervals = |seed| {
+            let mut scheduler = Scheduler::with_time_source(Duration::from_millis(1), MockTimeSource::new());
+            scheduler.seed(seed);
+            scheduler.backoff(Backoff::with_jitter(Duration::from_secs(1), 2.0, Duration::from_secs(60), 0.5).unwrap(), 1);
+            (0..5).map(|_| {
+                let next_in = scheduler.next_in();
+                scheduler.fast_forward(next_in);
+                match scheduler.next() {
+                    Some(Schedule::Current(fired)) => fired[0].interval,
+                    schedule => panic!("unexpected schedule: {:?}", schedule)
+                }
+            }).collect::<Vec<_>>()
+        };
+
+        assert_eq!(intervals(1), intervals(1));
+        assert!(intervals(1) != intervals(2));
+        for (step, interval) in intervals(3).into_iter().enumerate() {
+            let delay = Duration::from_secs(1 << step);
+            assert!(interval > delay / 2 && interval <= delay);
+        }
+    }
+
+    #[test]
+    fn jitter_splay() {
+        let first_runs = |seed| {
+            let mut scheduler = Scheduler::with_time_source(Duration::from_millis(1), MockTimeSource::new());
+            scheduler.seed(seed);
+            (0..10).map(|token| {
+                let id = scheduler.every(Duration::from_secs(60), token);
+                assert!(scheduler.set_jitter(id, Jitter::Splay(Duration::from_secs(60))));
+                scheduler.task(id).unwrap().schedule()
+            }).collect::<Vec<_>>()
+        };
+
+        let runs = first_runs(1);
+        assert_eq!(runs, first_runs(1));
+        assert!(runs != first_runs(2));
+        assert!(runs.iter().all(|&run| run >= Duration::from_secs(60) && run < Duration::from_secs(2 * 60)));
+        assert!(runs.iter().any(|&run| run != runs[0]));
+
+        // phase stays shifted
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(1), MockTimeSource::new());
+        let id = scheduler.every(Duration::from_secs(60), 1);
+        scheduler.set_jitter(id, Jitter::Splay(Duration::from_secs(60)));
+        let first = scheduler.task(id).unwrap().schedule();
+        scheduler.fast_forward(first);
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.task(id).unwrap().schedule(), first + Duration::from_secs(60));
+
+        assert!(!scheduler.set_jitter(TaskId(42), Jitter::Splay(Duration::from_secs(60))));
+    }
+
+    #[test]
+    fn jitter_paused_and_limits() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_secs(60), 1);
+        assert!(scheduler.pause_task(id));
+        assert!(scheduler.set_jitter(id, Jitter::PerRun(Duration::from_secs(10))));
+        assert!(scheduler.task(id).unwrap().run_jitter.is_some());
+        assert!(scheduler.resume_task(id));
+        assert!(scheduler.task(id).unwrap().schedule() >= Duration::from_secs(60));
+
+        let parked = scheduler.every_after_ack(Duration::from_secs(1), 2);
+        scheduler.fast_forward(Duration::from_secs(1));
+        scheduler.next();
+        assert!(scheduler.set_jitter(parked, Jitter::PerRun(Duration::from_secs(10))));
+        assert!(scheduler.task(parked).unwrap().run_jitter.is_some());
+
+        // jitter would move the task out of time point range
+        assert_eq!(scheduler.try_set_jitter(id, Jitter::Splay(weeks(30501))), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_set_jitter(id, Jitter::PerRun(Duration::new(u64::MAX, 0))), Err(SchedulerError::Overflow));
+        assert_eq!(scheduler.try_set_jitter(TaskId(42), Jitter::Splay(weeks(30500))), Ok(false));
+        assert!(scheduler.task(id).unwrap().schedule() < Duration::from_secs(60 + 10));
+    }
+
+    #[test]
+    fn jitter_per_run() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(1), MockTimeSource::new());
+        scheduler.seed(42);
+        let id = scheduler.every(Duration::from_secs(60), 1);
+        assert!(scheduler.set_jitter(id, Jitter::PerRun(Duration::from_secs(10))));
+
+        let mut runs = Vec::new();
+        for _ in 0..10 {
+            let next_in = scheduler.next_in();
+            scheduler.fast_forward(next_in);
+            match scheduler.next() {
+                Some(Schedule::Current(fired)) => runs.push(fired[0].scheduled),
+                schedule => panic!("unexpected schedule: {:?}", schedule)
+            }
+        }
+
+        // runs do not drift from their regular schedule
+        for (run, &scheduled) in runs.iter().enumerate() {
+            let regular = Duration::from_secs((run as u64 + 1) * 60);
+            assert!(scheduled >= regular && scheduled < regular + Duration::from_secs(10));
+        }
+        assert!(runs.windows(2).any(|runs| runs[1] - runs[0] != Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn every_after_ack() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every_after_ack(Duration::from_secs(2), 1);
+        assert!(!scheduler.ack(id));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+
+        // parked until acknowledged
+        assert_eq!(scheduler.next().tokens(), None);
+        assert!(scheduler.task(id).is_some());
+        scheduler.fast_forward(Duration::from_secs(5));
+        assert_eq!(scheduler.next().tokens(), None);
+
+        assert!(scheduler.ack(id));
+        assert!(!scheduler.ack(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(2));
+        scheduler.fast_forward(Duration::from_secs(2));
+        match scheduler.next() {
+            Some(Schedule::Current(fired)) => {
+                assert_eq!(fired[0].scheduled, Duration::from_secs(9));
+                assert_eq!(fired[0].iteration, 1);
+            },
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+
+        // overrun runs once
+        assert!(scheduler.ack(id));
+        scheduler.fast_forward(Duration::from_secs(10));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![1])));
+        assert_eq!(scheduler.next().tokens(), None);
+
+        assert_eq!(scheduler.cancel_task(id), Some(1));
+        assert!(!scheduler.ack(id));
+        assert!(scheduler.task(id).is_none());
+    }
+
+    #[test]
+    fn set_interval() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let keep = scheduler.every(Duration::from_secs(30), 1);
+        let last = scheduler.every(Duration::from_secs(30), 2);
+        scheduler.fast_forward(Duration::from_secs(30));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1, 2])));
+        scheduler.fast_forward(Duration::from_secs(10));
+
+        assert!(scheduler.set_interval(keep, Duration::from_secs(5), IntervalChange::KeepNextRun));
+        assert!(scheduler.set_interval(last, Duration::from_secs(5), IntervalChange::FromLastRun));
+        assert_eq!(scheduler.task(keep).unwrap().schedule(), Duration::from_secs(60));
+        assert_eq!(scheduler.task(last).unwrap().schedule(), Duration::from_secs(35));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+
+        scheduler.fast_forward(Duration::from_secs(20));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2, 2, 2])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1, 2])));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(5));
+
+        let id = scheduler.cron("* * * * *", 3).unwrap();
+        assert!(!scheduler.set_interval(id, Duration::from_secs(5), IntervalChange::KeepNextRun));
+        assert!(!scheduler.set_interval(TaskId(42), Duration::from_secs(5), IntervalChange::KeepNextRun));
+    }
+
+    #[test]
+    fn timing_wheel_task_queue() {
+        let mut scheduler = Scheduler::with_task_queue(Duration::from_secs(1), MockTimeSource::new(), TimingWheelTaskQueue::new());
+
+        scheduler.after(Duration::from_secs(0), 0);
+        let id = scheduler.after(Duration::from_secs(1), 1);
+        scheduler.every(Duration::from_secs(100), 2);
+
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![0])));
+        assert_eq!(scheduler.cancel_task(id), Some(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(100))));
+
+        scheduler.fast_forward(Duration::from_secs(350));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Overrun(vec![2, 2, 2])));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(50))));
+    }
+
+    #[test]
+    fn timing_wheel_task_queue_same_as_btree() {
+        let mut btree = Scheduler::with_time_source(Duration::from_millis(10), MockTimeSource::new());
+        let mut wheel = Scheduler::with_task_queue(Duration::from_millis(10), MockTimeSource::new(), TimingWheelTaskQueue::new());
+
+        // simple LCG so the test is repeatable
+        let mut seed = 42u64;
+        let mut random = move |max: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % max
+        };
+
+        for round in 0..1000 {
+            match random(4) {
+                0 => {
+                    let duration = Duration::from_millis(random(100_000));
+                    assert_eq!(btree.after(duration, round), wheel.after(duration, round));
+                },
+                1 => {
+                    let duration = Duration::from_millis(random(10_000) + 1);
+                    assert_eq!(btree.every(duration, round), wheel.every(duration, round));
+                },
+                2 => {
+                    let id = TaskId(random(round as u64 + 1));
+                    assert_eq!(btree.cancel_task(id), wheel.cancel_task(id));
+                },
+                _ => {
+                    let duration = Duration::from_millis(random(5_000));
+                    btree.fast_forward(duration);
+                    wheel.fast_forward(duration);
+                }
+            }
+            assert_eq!(btree.next(), wheel.next());
+        }
     }
 }
@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use time_source::*;
+use scheduler::*;
+use task_queue::*;
+use task::*;
+
+// Task taken out of the schedule with time it was paused at
+pub(super) struct PausedTask<Token> where Token: Clone {
+    pub(super) task: Task<Token>,
+    pub(super) paused_at: Duration,
+    // paused with pause_task() and not just because whole scheduler is paused
+    pub(super) explicit: bool
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    // Take the task out of the schedule keeping time remaining to its next run.
+    // Returns false if task is not scheduled.
+    pub fn pause_task(&mut self, id: TaskId) -> bool {
+        if let Some(paused) = self.paused.get_mut(&id) {
+            paused.explicit = true;
+            return true;
+        }
+
+        match self.tasks.remove(id) {
+            Some(task) => {
+                let now = self.time_source.now();
+                self.paused.insert(id, PausedTask {
+                    task,
+                    paused_at: now,
+                    explicit: true
+                });
+                true
+            },
+            None => false
+        }
+    }
+
+    // Schedule paused task with its remaining time counted from now; task stays paused until whole
+    // scheduler is resumed if it is paused. Returns false if task was not paused with pause_task().
+    pub fn resume_task(&mut self, id: TaskId) -> bool {
+        self.try_resume_task(id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // task stays paused if its remaining time counted from now does not fit in time points
+    pub fn try_resume_task(&mut self, id: TaskId) -> Result<bool, SchedulerError> {
+        match self.paused.get(&id) {
+            Some(paused) if paused.explicit => (),
+            _ => return Ok(false)
+        }
+
+        if self.paused_at.is_none() {
+            let task = self.unpaused(&self.paused[&id])?;
+            self.paused.remove(&id);
+            self.schedule(task);
+        } else if let Some(paused) = self.paused.get_mut(&id) {
+            paused.explicit = false;
+        }
+        Ok(true)
+    }
+
+    // Pause all tasks including ones scheduled while paused
+    pub fn pause(&mut self) {
+        if self.paused_at.is_some() {
+            return;
+        }
+
+        let now = self.time_source.now();
+        self.paused_at = Some(now);
+        for task in self.tasks.drain() {
+            self.paused.insert(task.id, PausedTask {
+                task,
+                paused_at: now,
+                explicit: false
+            });
+        }
+    }
+
+    // Resume tasks that were not paused with pause_task()
+    pub fn resume(&mut self) {
+        self.try_resume().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // nothing is resumed if remaining time of any of the tasks counted from now does not fit in time points
+    pub fn try_resume(&mut self) -> Result<(), SchedulerError> {
+        if self.paused_at.is_none() {
+            return Ok(());
+        }
+
+        let mut tasks = self.paused.values()
+            .filter(|paused| !paused.explicit)
+            .map(|paused| self.unpaused(paused))
+            .collect::<Result<Vec<_>, _>>()?;
+        // keep order of tasks due at the same time
+        tasks.sort_by_key(|task| task.id);
+
+        self.paused_at = None;
+        for task in tasks {
+            self.paused.remove(&task.id);
+            self.schedule(task);
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn is_task_paused(&self, id: TaskId) -> bool {
+        self.paused.contains_key(&id)
+    }
+
+    // paused task with its remaining time counted from now
+    fn unpaused(&self, paused: &PausedTask<Token>) -> Result<Task<Token>, SchedulerError> {
+        let shift = self.time_source.now().checked_sub(paused.paused_at).unwrap_or_default();
+        let task = Task {
+            due: paused.task.due.checked_add(shift).ok_or(SchedulerError::Overflow)?,
+            .. paused.task.clone()
+        };
+        self.try_to_time_point(task.schedule())?;
+        Ok(task)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::time::Duration;
+
+    #[test]
+    fn pause_task() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.every(Duration::from_secs(3), 1);
+        scheduler.after(Duration::from_secs(10), 2);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert!(scheduler.pause_task(id));
+        assert!(scheduler.is_task_paused(id));
+        assert!(scheduler.task(id).is_some());
+        assert_eq!(scheduler.next_in(), Duration::from_secs(9));
+
+        scheduler.fast_forward(Duration::from_secs(5));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::NextIn(Duration::from_secs(4))));
+
+        // remaining 2 seconds are counted from now
+        assert!(scheduler.resume_task(id));
+        assert!(!scheduler.resume_task(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(2));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(2));
+
+        assert!(!scheduler.pause_task(TaskId(42)));
+        assert!(scheduler.pause_task(id));
+        assert_eq!(scheduler.cancel_task(id), Some(1));
+        assert!(!scheduler.is_task_paused(id));
+    }
+
+    #[test]
+    fn pause_scheduler() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.every(Duration::from_secs(3), 1);
+        let id = scheduler.after(Duration::from_secs(4), 2);
+        scheduler.fast_forward(Duration::from_secs(1));
+        scheduler.pause();
+        assert!(scheduler.is_paused());
+        assert_eq!(scheduler.next().tokens(), None);
+
+        scheduler.fast_forward(Duration::from_secs(10));
+        assert!(scheduler.pause_task(id));
+        scheduler.after(Duration::from_secs(1), 3);
+        scheduler.fast_forward(Duration::from_secs(10));
+        assert_eq!(scheduler.next().tokens(), None);
+
+        scheduler.resume();
+        assert!(!scheduler.is_paused());
+        assert!(scheduler.is_task_paused(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![3])));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1])));
+
+        // had 3 seconds remaining when scheduler was paused
+        assert!(scheduler.resume_task(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn resume_task_while_scheduler_paused() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.after(Duration::from_secs(2), 1);
+        scheduler.pause_task(id);
+        scheduler.pause();
+        assert!(scheduler.resume_task(id));
+        assert!(scheduler.is_task_paused(id));
+        assert_eq!(scheduler.next().tokens(), None);
+
+        scheduler.fast_forward(Duration::from_secs(5));
+        scheduler.resume();
+        assert_eq!(scheduler.next_in(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn resume_overflow() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let id = scheduler.after(Duration::from_secs(10), 1);
+        scheduler.after(Duration::from_secs(1), 2);
+        scheduler.pause();
+        scheduler.fast_forward(Duration::from_nanos(u64::MAX) - Duration::from_secs(5));
+
+        // 1 would be due past the last time point
+        assert_eq!(scheduler.try_resume(), Err(SchedulerError::Overflow));
+        assert!(scheduler.is_paused());
+        assert_eq!(scheduler.cancel_task(id), Some(1));
+        assert_eq!(scheduler.try_resume(), Ok(()));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+
+        let id = scheduler.after(Duration::from_secs(4), 3);
+        assert!(scheduler.pause_task(id));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.try_resume_task(id), Err(SchedulerError::Overflow));
+        assert!(scheduler.is_task_paused(id));
+    }
+}
@@ -0,0 +1,290 @@
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+
+use time_source::*;
+use task::*;
+use task_queue::*;
+use btree_task_queue::*;
+use duration::*;
+use scheduler::*;
+
+struct Shared<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    scheduler: Scheduler<Token, TS, Q>,
+    // a waiter sleeps with abortable wait of a copy of the time source
+    waiting: bool,
+    // wait was aborted for a task that became due earlier; cleared by the waiter that was aborted
+    aborted: bool
+}
+
+struct State<Token, TS, Q> where TS: TimeSource + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
+    shared: Mutex<Shared<Token, TS, Q>>,
+    // notified when the scheduler was used by another thread or the waiter woke up
+    changed: Condvar,
+    abort_handle: TS::AbortHandle
+}
+
+// Scheduler shared between threads; waiter is woken up when a task becomes due earlier
+pub struct SharedScheduler<Token, TS, Q = BTreeTaskQueue<Token>> where TS: TimeSource + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
+    inner: Arc<State<Token, TS, Q>>
+}
+
+impl<Token, TS, Q> Clone for SharedScheduler<Token, TS, Q> where TS: TimeSource + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
+    fn clone(&self) -> Self {
+        SharedScheduler {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+// copies of the time source have to share the clock and abort handles
+impl<Token, TS, Q> SharedScheduler<Token, TS, Q> where TS: TimeSource + AbortableWait + Clone, Token: Clone, Q: TaskQueue<Token> {
+    pub fn new(scheduler: Scheduler<Token, TS, Q>) -> SharedScheduler<Token, TS, Q> {
+        let abort_handle = scheduler.time_source.abort_handle();
+        SharedScheduler {
+            inner: Arc::new(State {
+                shared: Mutex::new(Shared {
+                    scheduler,
+                    waiting: false,
+                    aborted: false
+                }),
+                changed: Condvar::new(),
+                abort_handle
+            })
+        }
+    }
+
+    pub fn after<D>(&self, duration: D, token: Token) -> TaskId where D: IntoDuration {
+        self.with_scheduler(|scheduler| scheduler.after(duration, token))
+    }
+
+    pub fn every<D>(&self, duration: D, token: Token) -> TaskId where D: IntoDuration {
+        self.with_scheduler(|scheduler| scheduler.every(duration, token))
+    }
+
+    pub fn try_after<D>(&self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        self.with_scheduler(|scheduler| scheduler.try_after(duration, token))
+    }
+
+    pub fn try_every<D>(&self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        self.with_scheduler(|scheduler| scheduler.try_every(duration, token))
+    }
+
+    pub fn every_after_ack<D>(&self, duration: D, token: Token) -> TaskId where D: IntoDuration {
+        self.with_scheduler(|scheduler| scheduler.every_after_ack(duration, token))
+    }
+
+    pub fn ack(&self, id: TaskId) -> bool {
+        self.with_scheduler(|scheduler| scheduler.ack(id))
+    }
+
+    pub fn cancel_task(&self, id: TaskId) -> Option<Token> {
+        self.with_scheduler(|scheduler| scheduler.cancel_task(id))
+    }
+
+    pub fn cancel(&self, token: &Token) where Token: PartialEq<Token> {
+        self.with_scheduler(|scheduler| scheduler.cancel(token))
+    }
+
+    pub fn next_in(&self) -> Duration {
+        self.with_scheduler(|scheduler| scheduler.next_in())
+    }
+
+    // Run f with exclusive access to the scheduler; waiter is aborted if a task became due earlier
+    pub fn with_scheduler<F, R>(&self, f: F) -> R where F: FnOnce(&mut Scheduler<Token, TS, Q>) -> R {
+        let mut shared = self.inner.shared.lock().unwrap();
+        let first = shared.scheduler.tasks.first_time_point();
+        let result = f(&mut shared.scheduler);
+        let earlier = match (first, shared.scheduler.tasks.first_time_point()) {
+            (Some(first), Some(time_point)) => time_point < first,
+            (None, Some(_)) => true,
+            (_, None) => false
+        };
+
+        if earlier && shared.waiting && !shared.aborted {
+            shared.aborted = true;
+            self.inner.abort_handle.abort();
+        }
+        drop(shared);
+        self.inner.changed.notify_all();
+        result
+    }
+
+    pub fn try(&self) -> Option<WaitResult<Token>> {
+        self.with_scheduler(|scheduler| scheduler.try())
+    }
+
+    // aborts abortable_wait(); does not affect wait()
+    pub fn abort_handle(&self) -> TS::AbortHandle {
+        self.inner.shared.lock().unwrap().scheduler.time_source.abort_handle()
+    }
+
+    // Like Scheduler::wait but blocks when scheduler is empty until a task is scheduled
+    pub fn wait(&self) -> WaitResult<Token> {
+        loop {
+            match self.next_schedule(true) {
+                Ok(Some(Schedule::Current(fired))) => return Ok(fired),
+                Ok(Some(Schedule::Overrun(overrun))) => return Err(WaitError::Overrun(overrun)),
+                Ok(Some(Schedule::ClockJump(offset))) => return Err(WaitError::ClockJump(offset)),
+                // aborted with abort_handle(); just re-evaluate the schedule
+                Err(WaitAbortedError) => (),
+                Ok(Some(Schedule::NextIn(_))) | Ok(None) => unreachable!()
+            }
+        }
+    }
+
+    // Like Scheduler::abortable_wait; wait is not aborted by other threads using the scheduler
+    pub fn abortable_wait(&self) -> Result<Vec<Fired<Token>>, AbortableWaitError<Fired<Token>>> {
+        match self.next_schedule(false) {
+            Ok(Some(Schedule::Current(fired))) => Ok(fired),
+            Ok(Some(Schedule::Overrun(overrun))) => Err(AbortableWaitError::Overrun(overrun)),
+            Ok(Some(Schedule::ClockJump(offset))) => Err(AbortableWaitError::ClockJump(offset)),
+            Ok(None) => Err(AbortableWaitError::Empty),
+            Err(WaitAbortedError) => Err(AbortableWaitError::Aborted),
+            Ok(Some(Schedule::NextIn(_))) => unreachable!()
+        }
+    }
+
+    // Wait for schedule other than NextIn; None if scheduler is empty and blocking is not requested.
+    // Fails if the wait was aborted with abort_handle().
+    fn next_schedule(&self, block_when_empty: bool) -> Result<Option<Schedule<Fired<Token>>>, WaitAbortedError> {
+        let mut shared = self.inner.shared.lock().unwrap();
+
+        loop {
+            // one waiter at a time so that aborts reach the right one
+            while shared.waiting {
+                shared = self.inner.changed.wait(shared).unwrap();
+            }
+
+            match shared.scheduler.next() {
+                Some(Schedule::NextIn(duration)) => {
+                    let mut time_source = shared.scheduler.time_source.clone();
+                    shared.waiting = true;
+                    drop(shared);
+                    let result = time_source.abortable_wait(duration);
+
+                    shared = self.inner.shared.lock().unwrap();
+                    shared.waiting = false;
+                    self.inner.changed.notify_all();
+                    if result.is_err() {
+                        if !shared.aborted {
+                            return Err(WaitAbortedError);
+                        }
+                        shared.aborted = false;
+                    }
+                    // otherwise abort that came too late is still pending and ends the next wait
+                },
+                Some(schedule) => return Ok(Some(schedule)),
+                None if block_when_empty => shared = self.inner.changed.wait(shared).unwrap(),
+                None => return Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_time_source::*;
+    use test_helpers::*;
+    use std::time::Duration;
+    use std::thread::{spawn, yield_now};
+
+    fn shared_scheduler(mode: MockWaitMode) -> (SharedScheduler<i32, MockTimeSourceWait>, MockClock) {
+        let clock = MockClock::new();
+        let time_source = MockTimeSourceWait::with_clock(clock.clone(), mode);
+        (SharedScheduler::new(Scheduler::with_time_source(Duration::from_millis(100), time_source)), clock)
+    }
+
+    #[test]
+    fn wake_on_earlier_task() {
+        let (scheduler, clock) = shared_scheduler(MockWaitMode::Manual);
+        scheduler.after(Duration::from_secs(20), 1);
+
+        let waiter = scheduler.clone();
+        let handle = spawn(move || waiter.wait().tokens());
+
+        // waiter sleeping toward 20s is aborted to schedule this one
+        scheduler.after(Duration::from_secs(1), 2);
+        clock.advance(Duration::from_secs(1));
+
+        assert_eq!(handle.join().unwrap(), Ok(vec![2]));
+        assert_eq!(clock.now(), Duration::from_secs(1));
+        scheduler.cancel(&1);
+        assert_eq!(scheduler.try().tokens(), Some(Err(WaitError::Empty)));
+    }
+
+    #[test]
+    fn wait_for_task_when_empty() {
+        let (scheduler, clock) = shared_scheduler(MockWaitMode::AutoAdvance);
+
+        let waiter = scheduler.clone();
+        let handle = spawn(move || (waiter.wait().tokens(), waiter.wait().tokens()));
+
+        scheduler.every(Duration::from_secs(1), 1);
+
+        assert_eq!(handle.join().unwrap(), (Ok(vec![1]), Ok(vec![1])));
+        assert_eq!(clock.now(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn later_task_does_not_lose_tokens() {
+        let (scheduler, clock) = shared_scheduler(MockWaitMode::Manual);
+        scheduler.after(Duration::from_secs(5), 1);
+
+        let waiter = scheduler.clone();
+        let handle = spawn(move || waiter.wait().tokens());
+
+        scheduler.after(Duration::from_secs(7), 2);
+        scheduler.with_scheduler(|scheduler| scheduler.after(Duration::from_secs(1), 3));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(handle.join().unwrap(), Ok(vec![3]));
+
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![1]));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![2]));
+    }
+
+    #[test]
+    fn later_task_does_not_abort_wait() {
+        let (scheduler, clock) = shared_scheduler(MockWaitMode::Manual);
+        scheduler.after(Duration::from_secs(5), 1);
+
+        let waiter = scheduler.clone();
+        let handle = spawn(move || waiter.wait().tokens());
+        while !scheduler.inner.shared.lock().unwrap().waiting {
+            yield_now();
+        }
+
+        scheduler.after(Duration::from_secs(7), 2);
+        scheduler.every(Duration::from_secs(5), 3);
+        assert!(!scheduler.inner.shared.lock().unwrap().aborted);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(handle.join().unwrap(), Ok(vec![1, 3]));
+    }
+
+    #[test]
+    fn external_abort() {
+        let (scheduler, clock) = shared_scheduler(MockWaitMode::Manual);
+        scheduler.after(Duration::from_secs(5), 1);
+        let abort_handle = scheduler.abort_handle();
+
+        let waiter = scheduler.clone();
+        let handle = spawn(move || waiter.abortable_wait().tokens());
+        while !scheduler.inner.shared.lock().unwrap().waiting {
+            yield_now();
+        }
+
+        abort_handle.abort();
+        assert_eq!(handle.join().unwrap(), Err(AbortableWaitError::Aborted));
+
+        // plain wait carries on
+        abort_handle.abort();
+        let waiter = scheduler.clone();
+        let handle = spawn(move || waiter.wait().tokens());
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(handle.join().unwrap(), Ok(vec![1]));
+        assert_eq!(scheduler.abortable_wait().tokens(), Err(AbortableWaitError::Empty));
+    }
+}
@@ -0,0 +1,540 @@
+use std::cmp;
+use std::convert::TryFrom;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use time_source::*;
+use task::*;
+use cron::*;
+use backoff::*;
+use rng::*;
+use task_queue::*;
+use scheduler::*;
+use scheduler::pause::PausedTask;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BondSnapshot {
+    OneOff,
+    Perpetual,
+    FixedDelay,
+    Repeat(u64),
+    // time left until the deadline in nanoseconds
+    Until(i128),
+    // re-anchored to wall clock on restore
+    Cron(CronSchedule),
+    // wall clock time of the run is taken from remaining time on restore
+    At,
+    Backoff {
+        initial: i128,
+        multiplier: f64,
+        max: i128,
+        jitter: f64,
+        // current delay before jitter
+        delay: i128,
+        rng: JitterRng
+    }
+}
+
+// Task timing relative to when snapshot was taken, in nanoseconds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskSnapshot<Token> {
+    pub id: TaskId,
+    pub token: Token,
+    // time left until the task is due; negative if it was overdue already
+    pub remaining: i128,
+    pub interval: i128,
+    pub bond: BondSnapshot,
+    pub overrun_policy: OverrunPolicy,
+    // maximum and current random delay of the run and generator of following delays
+    pub run_jitter: Option<(i128, i128, JitterRng)>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub iteration: u64,
+    // fired fixed delay task waiting for acknowledgement; remaining time does not apply
+    pub parked: bool,
+    // paused task or all tasks of paused scheduler; remaining time is counted from the pause and
+    // they are restored as tasks paused with pause_task()
+    #[serde(default)]
+    pub paused: bool
+}
+
+// Serializable scheduler tasks
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerSnapshot<Token> {
+    // wall clock time the snapshot was taken at as seconds and nanoseconds since UNIX epoch
+    pub taken_at: (i64, i32),
+    pub next_task_id: u64,
+    pub tasks: Vec<TaskSnapshot<Token>>
+}
+
+impl<Token> SchedulerSnapshot<Token> {
+    pub fn taken_at(&self) -> SystemTime {
+        self.try_taken_at().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_taken_at(&self) -> Result<SystemTime, SchedulerError> {
+        let (sec, nsec) = self.taken_at;
+        let time = if sec >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(sec as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(sec.unsigned_abs()))
+        };
+        time.and_then(|time| time.checked_add(Duration::from_nanos(cmp::max(nsec, 0) as u64)))
+            .ok_or(SchedulerError::Overflow)
+    }
+}
+
+// any Duration fits in i128 nanoseconds
+fn nanoseconds(duration: Duration) -> i128 {
+    duration.as_nanos() as i128
+}
+
+// signed time from one time to another in nanoseconds
+fn nanoseconds_between(from: Duration, to: Duration) -> i128 {
+    nanoseconds(to) - nanoseconds(from)
+}
+
+// negative durations of malformed snapshot are taken for zero
+fn duration(nanoseconds: i128) -> Result<Duration, SchedulerError> {
+    let nanoseconds = cmp::max(nanoseconds, 0);
+    let secs = u64::try_from(nanoseconds / 1_000_000_000).map_err(|_| SchedulerError::Overflow)?;
+    Ok(Duration::new(secs, (nanoseconds % 1_000_000_000) as u32))
+}
+
+// time source time in nanoseconds of time that was given remaining time away when snapshot was taken given time ago
+fn time_after(now: Duration, remaining: i128, ago: Duration) -> Result<i128, SchedulerError> {
+    nanoseconds(now).checked_add(remaining)
+        .and_then(|time| time.checked_sub(nanoseconds(ago)))
+        .ok_or(SchedulerError::Overflow)
+}
+
+// task restored in time frame moved forward by shift so that task overdue since before time source zero is
+// due at zero and its missed runs can be counted
+struct RestoredTask<Token> where Token: Clone {
+    task: Task<Token>,
+    shift: Duration,
+    parked: bool,
+    paused: bool,
+    tags: Vec<String>
+}
+
+// task restored in time frame moved forward by given duration back in time frame of the time source;
+// times before time source zero are taken for zero
+fn shift_back<Token>(task: Task<Token>, shift: Duration) -> Task<Token> where Token: Clone {
+    Task {
+        due: task.due.checked_sub(shift).unwrap_or_default(),
+        bond: match task.bond {
+            TaskBond::Until(deadline) => TaskBond::Until(deadline.checked_sub(shift).unwrap_or_default()),
+            TaskBond::Cron(schedule, epoch) => TaskBond::Cron(schedule, epoch + shift),
+            bond => bond
+        },
+        .. task
+    }
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    pub fn snapshot(&self) -> SchedulerSnapshot<Token> {
+        let now = self.time_source.now();
+        let taken_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut parked: Vec<&Task<Token>> = self.parked.values().collect();
+        parked.sort_by_key(|task| task.id);
+        let mut paused: Vec<&PausedTask<Token>> = self.paused.values().collect();
+        paused.sort_by_key(|paused| paused.task.id);
+        let tasks = self.tasks.tasks().into_iter().map(|task| (task, false, None))
+            .chain(parked.into_iter().map(|task| (task, true, None)))
+            .chain(paused.into_iter().map(|paused| (&paused.task, false, Some(paused.paused_at))));
+
+        SchedulerSnapshot {
+            taken_at: (taken_at.as_secs() as i64, taken_at.subsec_nanos() as i32),
+            next_task_id: self.next_task_id,
+            tasks: tasks.map(|(task, parked, paused_at)| TaskSnapshot {
+                id: task.id,
+                token: task.token.clone(),
+                remaining: nanoseconds_between(paused_at.unwrap_or(now), task.schedule()),
+                interval: nanoseconds(task.interval),
+                bond: match task.bond {
+                    TaskBond::OneOff => BondSnapshot::OneOff,
+                    TaskBond::Perpetual => BondSnapshot::Perpetual,
+                    TaskBond::FixedDelay => BondSnapshot::FixedDelay,
+                    TaskBond::Repeat(count) => BondSnapshot::Repeat(count),
+                    TaskBond::Until(deadline) => BondSnapshot::Until(nanoseconds_between(now, deadline)),
+                    TaskBond::Cron(ref schedule, _) => BondSnapshot::Cron(schedule.clone()),
+                    TaskBond::At(_) => BondSnapshot::At,
+                    TaskBond::Backoff(ref backoff, delay, ref rng) => BondSnapshot::Backoff {
+                        initial: nanoseconds(backoff.initial),
+                        multiplier: backoff.multiplier,
+                        max: nanoseconds(backoff.max),
+                        jitter: backoff.jitter,
+                        delay: nanoseconds(delay),
+                        rng: rng.clone()
+                    }
+                },
+                overrun_policy: task.overrun_policy,
+                run_jitter: task.run_jitter.as_ref().map(|run_jitter|
+                    (nanoseconds(run_jitter.max), nanoseconds(run_jitter.offset), run_jitter.rng.clone())
+                ),
+                priority: task.priority,
+                tags: self.tags.get(&task.id).cloned().unwrap_or_default(),
+                iteration: task.iteration,
+                parked,
+                paused: paused_at.is_some()
+            }).collect()
+        }
+    }
+
+    // Restore tasks into empty scheduler measuring time passed since the snapshot by wall clock; see restore_after
+    pub fn restore(&mut self, snapshot: SchedulerSnapshot<Token>) -> Result<Vec<Fired<Token>>, SchedulerError> {
+        let downtime = SystemTime::now().duration_since(snapshot.try_taken_at()?).unwrap_or_default();
+        self.restore_after(snapshot, downtime)
+    }
+
+    // Restore tasks into empty scheduler as if given time has passed since the snapshot was taken.
+    // Runs that were due in the meantime are not scheduled but returned, one record per task with
+    // number of missed runs; repeating tasks continue with their first run that was not missed.
+    // Nothing is restored if the scheduler is not empty or the snapshot has invalid task, task with
+    // time that does not fit in time points or more than one task with the same id.
+    pub fn restore_after(&mut self, snapshot: SchedulerSnapshot<Token>, downtime: Duration) -> Result<Vec<Fired<Token>>, SchedulerError> {
+        if !self.is_empty() {
+            return Err(SchedulerError::TaskQueueNotEmpty);
+        }
+
+        let now = self.time_source.now();
+        // wall clock time of time source zero
+        let epoch = snapshot.try_taken_at()?.checked_add(downtime)
+            .and_then(|time| time.checked_sub(now))
+            .ok_or(SchedulerError::Overflow)?;
+        // tasks due in current time point are not missed
+        let current = self.to_duration(self.to_time_point(now));
+
+        let mut ids = HashSet::new();
+        let mut restored = Vec::with_capacity(snapshot.tasks.len());
+        for task in snapshot.tasks {
+            if !ids.insert(task.id) {
+                return Err(SchedulerError::DuplicateTaskId(task.id));
+            }
+            restored.push(self.restored_task(task, now, downtime, epoch)?);
+        }
+
+        self.next_task_id = cmp::max(self.next_task_id, snapshot.next_task_id);
+        let mut missed = Vec::new();
+
+        for RestoredTask { task, shift, parked, paused, tags } in restored {
+            let id = task.id;
+
+            if parked {
+                self.parked.insert(task.id, shift_back(task, shift));
+            } else if paused {
+                self.paused.insert(task.id, PausedTask {
+                    task: shift_back(task, shift),
+                    paused_at: now,
+                    explicit: true
+                });
+            } else if task.schedule() >= current + shift {
+                self.schedule(shift_back(task, shift));
+            } else {
+                let (runs, next) = self.runs_before(&task, current + shift);
+                let mut fired = Fired::new(&task, now + shift, runs, next.is_none());
+                fired.scheduled = fired.scheduled.checked_sub(shift).unwrap_or_default();
+                fired.fired = now;
+                missed.push(fired);
+                if let Some(next) = next {
+                    self.schedule(shift_back(next, shift));
+                }
+            }
+
+            // tasks that will not run again are not tagged
+            for tag in tags {
+                self.tag(id, &tag);
+            }
+        }
+        Ok(missed)
+    }
+
+    fn restored_task(&self, task: TaskSnapshot<Token>, now: Duration, downtime: Duration, epoch: SystemTime) -> Result<RestoredTask<Token>, SchedulerError> {
+        let parked = task.parked;
+        let paused = task.paused;
+        let tags = task.tags;
+        let run_jitter = match task.run_jitter {
+            Some((max, offset, rng)) => Some(RunJitter {
+                max: duration(max)?,
+                offset: duration(offset)?,
+                rng
+            }),
+            None => None
+        };
+        let offset = run_jitter.as_ref().map(|run_jitter| run_jitter.offset).unwrap_or_default();
+        // regular schedule in nanoseconds of time source; paused tasks did not run down their remaining time
+        let due = time_after(now, task.remaining, if paused { Duration::new(0, 0) } else { downtime })?
+            .checked_sub(nanoseconds(offset))
+            .ok_or(SchedulerError::Overflow)?;
+        let shift = if due < 0 { due.saturating_neg() } else { 0 };
+        let due = duration(cmp::max(due, 0))?;
+        let shifted_epoch = epoch.checked_sub(duration(shift)?).ok_or(SchedulerError::Overflow)?;
+
+        let task = Task {
+            id: task.id,
+            interval: duration(task.interval)?,
+            due,
+            token: task.token,
+            bond: match task.bond {
+                BondSnapshot::OneOff => TaskBond::OneOff,
+                BondSnapshot::Perpetual => TaskBond::Perpetual,
+                BondSnapshot::FixedDelay => TaskBond::FixedDelay,
+                BondSnapshot::Repeat(count) => TaskBond::Repeat(count),
+                BondSnapshot::Until(remaining) => {
+                    let deadline = time_after(now, remaining, downtime)?.checked_add(shift).ok_or(SchedulerError::Overflow)?;
+                    TaskBond::Until(duration(deadline)?)
+                },
+                BondSnapshot::Cron(schedule) => TaskBond::Cron(schedule, shifted_epoch),
+                BondSnapshot::At => TaskBond::At(shifted_epoch.checked_add(due).ok_or(SchedulerError::Overflow)?),
+                BondSnapshot::Backoff { initial, multiplier, max, jitter, delay, rng } => TaskBond::Backoff(
+                    Backoff::with_jitter(duration(initial)?, multiplier, duration(max)?, jitter).map_err(SchedulerError::InvalidBackoff)?,
+                    duration(delay)?,
+                    rng
+                )
+            },
+            overrun_policy: task.overrun_policy,
+            run_jitter,
+            priority: task.priority,
+            iteration: task.iteration
+        };
+        match task.bond {
+            TaskBond::Backoff(ref backoff, ..) if backoff.initial == Duration::new(0, 0) => return Err(SchedulerError::InvalidInterval),
+            TaskBond::Perpetual | TaskBond::FixedDelay | TaskBond::Repeat(_) | TaskBond::Until(_) if task.interval == Duration::new(0, 0) =>
+                return Err(SchedulerError::InvalidInterval),
+            _ => ()
+        }
+        self.try_to_time_point(task.schedule())?;
+        let shift = duration(shift)?;
+        // missed runs are counted in the shifted time frame
+        now.checked_add(shift).ok_or(SchedulerError::Overflow)?;
+
+        Ok(RestoredTask { task, shift, parked, paused, tags })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::time::Duration;
+    use serde_json;
+
+    fn scheduler() -> Scheduler<&'static str, MockTimeSource> {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.time_source.fast_forward(Duration::from_secs(100));
+        scheduler.after(Duration::from_secs(10), "after");
+        scheduler.every_tagged(Duration::from_secs(3), "every", &["tenant"]);
+        scheduler
+    }
+
+    fn round_trip(snapshot: &SchedulerSnapshot<&'static str>) -> SchedulerSnapshot<String> {
+        let json = serde_json::to_string(snapshot).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut scheduler = scheduler();
+        scheduler.time_source.fast_forward(Duration::from_secs(1));
+        let snapshot = scheduler.snapshot();
+
+        assert_eq!(snapshot.next_task_id, 2);
+        assert_eq!(snapshot.tasks.len(), 2);
+        assert_eq!(snapshot.tasks[0].token, "every");
+        assert_eq!(snapshot.tasks[0].remaining, Duration::from_secs(2).as_nanos() as i128);
+        assert_eq!(snapshot.tasks[0].bond, BondSnapshot::Perpetual);
+        assert_eq!(snapshot.tasks[1].token, "after");
+        assert_eq!(snapshot.tasks[1].remaining, Duration::from_secs(9).as_nanos() as i128);
+        assert_eq!(snapshot.tasks[1].bond, BondSnapshot::OneOff);
+
+        let restored = round_trip(&snapshot);
+        assert_eq!(restored.tasks[1].token, "after");
+        assert_eq!(restored.taken_at, snapshot.taken_at);
+    }
+
+    #[test]
+    fn restore() {
+        let snapshot = round_trip(&scheduler().snapshot());
+
+        // different time source epoch
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert!(scheduler.restore_after(snapshot, Duration::new(0, 0)).unwrap().is_empty());
+        assert_eq!(scheduler.next_in(), Duration::from_secs(3));
+
+        scheduler.time_source.fast_forward(Duration::from_secs(3));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec!["every".to_string()])));
+
+        // ids are kept and new ones do not collide
+        assert_eq!(scheduler.task(TaskId(0)).unwrap().token, "after");
+        assert_eq!(scheduler.tagged("tenant"), vec![TaskId(1)]);
+        assert_eq!(scheduler.after(Duration::from_secs(1), "new".to_string()), TaskId(2));
+    }
+
+    #[test]
+    fn restore_missed() {
+        let snapshot = round_trip(&scheduler().snapshot());
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let missed = scheduler.restore_after(snapshot, Duration::from_secs(11)).unwrap();
+        assert_eq!(missed.clone().tokens(), vec!["every".to_string(), "after".to_string()]);
+
+        // every was due at 3, 6 and 9 seconds and is next due 1 second from now
+        assert_eq!(missed[0].runs, 3);
+        assert_eq!(missed[0].lateness, Duration::from_secs(8));
+        assert_eq!(missed[1].runs, 1);
+        assert_eq!(missed[1].lateness, Duration::from_secs(1));
+
+        assert!(scheduler.task(TaskId(0)).is_none());
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn restore_parked() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let id = scheduler.every_after_ack(Duration::from_secs(1), "ack");
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec!["ack"])));
+        let snapshot = round_trip(&scheduler.snapshot());
+        assert!(snapshot.tasks[0].parked);
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert!(scheduler.restore_after(snapshot, Duration::from_secs(60 * 60)).unwrap().is_empty());
+        assert_eq!(scheduler.next().tokens(), None);
+        assert!(scheduler.ack(id));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn restore_paused() {
+        let mut scheduler = scheduler();
+        assert!(scheduler.pause_task(TaskId(0)));
+        scheduler.time_source.fast_forward(Duration::from_secs(5));
+        let snapshot = round_trip(&scheduler.snapshot());
+        assert_eq!(snapshot.tasks[1].token, "after");
+        assert!(snapshot.tasks[1].paused);
+        assert_eq!(snapshot.tasks[1].remaining, Duration::from_secs(10).as_nanos() as i128);
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        let missed = scheduler.restore_after(snapshot, Duration::from_secs(60 * 60)).unwrap();
+        assert_eq!(missed.tokens(), vec!["every".to_string()]);
+        assert!(scheduler.is_task_paused(TaskId(0)));
+
+        scheduler.time_source.fast_forward(Duration::from_secs(60 * 60));
+        assert!(scheduler.resume_task(TaskId(0)));
+        assert_eq!(scheduler.task(TaskId(0)).unwrap().schedule() - scheduler.time_source.now(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn restore_cron() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.cron("*/5 * * * *", "cron").unwrap();
+        let mut snapshot = scheduler.snapshot();
+        // taken at mock wall clock time 00:00; task is due at 00:05
+        snapshot.taken_at = (0, 0);
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.time_source.fast_forward(Duration::from_secs(1000));
+        // 00:05, 00:10 and 00:15 runs were missed
+        let missed = scheduler.restore_after(snapshot, Duration::from_secs(17 * 60)).unwrap();
+
+        assert_eq!(missed[0].runs, 3);
+        assert_eq!(missed.tokens(), vec!["cron"]);
+        // next is three minutes away on the wall clock
+        assert_eq!(scheduler.next_in(), Duration::from_secs(3 * 60));
+    }
+
+    #[test]
+    fn restore_at_time() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.at_time(UNIX_EPOCH + Duration::from_secs(60), "at");
+        let mut snapshot = round_trip(&scheduler.snapshot());
+        snapshot.taken_at = (0, 0);
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.time_source.fast_forward(Duration::from_secs(1000));
+        assert!(scheduler.restore_after(snapshot, Duration::from_secs(20)).unwrap().is_empty());
+        assert_eq!(scheduler.next_in(), Duration::from_secs(40));
+
+        // still due at 00:01:00 on the wall clock after it is stepped back by 10 seconds
+        scheduler.reanchor(ClockOffset::Backward(Duration::from_secs(10)));
+        assert_eq!(scheduler.next_in(), Duration::from_secs(50));
+        match scheduler.task(TaskId(0)).unwrap().bond {
+            TaskBond::At(time) => assert_eq!(time, UNIX_EPOCH + Duration::from_secs(60)),
+            ref bond => panic!("unexpected bond: {:?}", bond)
+        }
+    }
+
+    #[test]
+    fn restore_invalid() {
+        let mut scheduler = scheduler();
+        let snapshot = scheduler.snapshot();
+        assert_eq!(scheduler.restore_after(snapshot.clone(), Duration::new(0, 0)), Err(SchedulerError::TaskQueueNotEmpty));
+
+        let mut zero_interval = snapshot.clone();
+        zero_interval.tasks[0].interval = 0;
+        let mut snapshot = snapshot;
+        snapshot.tasks[1].bond = BondSnapshot::Backoff {
+            initial: Duration::from_secs(2).as_nanos() as i128,
+            multiplier: 2.0,
+            max: Duration::from_secs(1).as_nanos() as i128,
+            jitter: 0.0,
+            delay: Duration::from_secs(2).as_nanos() as i128,
+            rng: JitterRng::new(42)
+        };
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert_eq!(scheduler.restore_after(snapshot, Duration::new(0, 0)), Err(SchedulerError::InvalidBackoff(BackoffError::MaxLessThanInitial)));
+        assert!(scheduler.is_empty());
+
+        assert_eq!(scheduler.restore_after(zero_interval, Duration::new(0, 0)), Err(SchedulerError::InvalidInterval));
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn large_duration() {
+        // more nanoseconds than fit in i64
+        let far = Duration::from_secs(20000 * 7 * 24 * 60 * 60);
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.every(far, "far");
+        let snapshot = round_trip(&scheduler.snapshot());
+        assert_eq!(snapshot.tasks[0].remaining, far.as_nanos() as i128);
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert!(scheduler.restore_after(snapshot, Duration::new(0, 0)).unwrap().is_empty());
+        assert_eq!(scheduler.next_in(), far);
+    }
+
+    #[test]
+    fn restore_overflow() {
+        let snapshot = scheduler().snapshot();
+
+        // due past the last time point
+        let mut far = snapshot.clone();
+        far.tasks[1].remaining = i128::from(u64::MAX) + 1;
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert_eq!(scheduler.restore_after(far, Duration::new(0, 0)), Err(SchedulerError::Overflow));
+        assert!(scheduler.is_empty());
+
+        // does not fit in nanoseconds of time source
+        for remaining in &[i128::MAX, i128::MIN] {
+            let mut malformed = snapshot.clone();
+            malformed.tasks[1].remaining = *remaining;
+            assert_eq!(scheduler.restore_after(malformed, Duration::from_secs(1)), Err(SchedulerError::Overflow));
+            assert!(scheduler.is_empty());
+        }
+    }
+
+    #[test]
+    fn restore_duplicate_id() {
+        let mut snapshot = scheduler().snapshot();
+        snapshot.tasks[1].id = snapshot.tasks[0].id;
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        assert_eq!(scheduler.restore_after(snapshot, Duration::new(0, 0)), Err(SchedulerError::DuplicateTaskId(TaskId(1))));
+        assert!(scheduler.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::mpsc::{self, Sender, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use time_source::*;
+use task::*;
+use task_queue::*;
+use btree_task_queue::*;
+use duration::*;
+use scheduler::*;
+
+// Batches fired by a spawned scheduler
+pub type Batches<Token> = Receiver<Schedule<Fired<Token>>>;
+
+type Job<Token, TS, Q> = Box<dyn FnOnce(&mut Scheduler<Token, TS, Q>) + Send>;
+
+enum Command<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    Run(Job<Token, TS, Q>),
+    Shutdown
+}
+
+// Handle of a scheduler running on its own thread; dropping it stops the thread
+pub struct SchedulerHandle<Token, TS, Q = BTreeTaskQueue<Token>> where TS: TimeSource + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
+    commands: Sender<Command<Token, TS, Q>>,
+    abort_handle: TS::AbortHandle,
+    thread: Option<JoinHandle<Scheduler<Token, TS, Q>>>
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource + AbortableWait + Send + 'static, Token: Clone + Send + 'static, Q: TaskQueue<Token> + Send + 'static {
+    // Run the scheduler on a new thread sending Current and Overrun batches and reported clock jumps to
+    // the returned receiver. Thread stops when shut down with the handle or when the receiver is dropped.
+    pub fn spawn(self) -> (SchedulerHandle<Token, TS, Q>, Batches<Token>) {
+        let (commands, command_receiver) = mpsc::channel();
+        let (batch_sender, batches) = mpsc::channel();
+        let abort_handle = self.time_source.abort_handle();
+        let thread = thread::spawn(move || self.run(command_receiver, batch_sender));
+
+        (SchedulerHandle {
+            commands,
+            abort_handle,
+            thread: Some(thread)
+        }, batches)
+    }
+
+    fn run(mut self, commands: Receiver<Command<Token, TS, Q>>, batches: Sender<Schedule<Fired<Token>>>) -> Scheduler<Token, TS, Q> {
+        loop {
+            loop {
+                match commands.try_recv() {
+                    Ok(Command::Run(f)) => f(&mut self),
+                    Ok(Command::Shutdown) | Err(TryRecvError::Disconnected) => return self,
+                    Err(TryRecvError::Empty) => break
+                }
+            }
+
+            match self.next() {
+                None => match commands.recv() {
+                    Ok(Command::Run(f)) => f(&mut self),
+                    Ok(Command::Shutdown) | Err(_) => return self
+                },
+                Some(Schedule::NextIn(duration)) => {
+                    // aborted when there are commands to execute
+                    let _ = self.time_source.abortable_wait(duration);
+                },
+                Some(schedule) => if batches.send(schedule).is_err() {
+                    return self;
+                }
+            }
+        }
+    }
+}
+
+impl<Token, TS, Q> SchedulerHandle<Token, TS, Q> where TS: TimeSource + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
+    // Run f on the scheduler thread and return its result; fails if the thread has stopped
+    pub fn with_scheduler<F, R>(&self, f: F) -> Result<R, SchedulerError> where F: FnOnce(&mut Scheduler<Token, TS, Q>) -> R + Send + 'static, R: Send + 'static {
+        let (reply, result) = mpsc::channel();
+        self.commands.send(Command::Run(Box::new(move |scheduler| {
+            let _ = reply.send(f(scheduler));
+        }))).map_err(|_| SchedulerError::Disconnected)?;
+        self.abort_handle.abort();
+        result.recv().map_err(|_| SchedulerError::Disconnected)
+    }
+
+    pub fn after<D>(&self, duration: D, token: Token) -> TaskId where D: IntoDuration, Token: Send + 'static {
+        self.try_after(duration, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_after<D>(&self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration, Token: Send + 'static {
+        let duration = duration.into_duration()?;
+        self.with_scheduler(move |scheduler| scheduler.try_after(duration, token))?
+    }
+
+    pub fn every<D>(&self, duration: D, token: Token) -> TaskId where D: IntoDuration, Token: Send + 'static {
+        self.try_every(duration, token).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every<D>(&self, duration: D, token: Token) -> Result<TaskId, SchedulerError> where D: IntoDuration, Token: Send + 'static {
+        let duration = duration.into_duration()?;
+        self.with_scheduler(move |scheduler| scheduler.try_every(duration, token))?
+    }
+
+    // None if there is no such task or the thread has stopped
+    pub fn cancel_task(&self, id: TaskId) -> Option<Token> where Token: Send + 'static {
+        self.with_scheduler(move |scheduler| scheduler.cancel_task(id)).unwrap_or(None)
+    }
+
+    // all tasks in order of Scheduler::tasks(); empty if the thread has stopped
+    pub fn tasks(&self) -> Vec<Task<Token>> where Token: Send + 'static {
+        self.with_scheduler(|scheduler| scheduler.tasks().into_iter().cloned().collect()).unwrap_or_default()
+    }
+
+    // Stop the thread and get the scheduler back with its remaining tasks
+    pub fn shutdown(mut self) -> Scheduler<Token, TS, Q> {
+        self.stop().expect("scheduler thread already stopped")
+    }
+
+    fn stop(&mut self) -> Option<Scheduler<Token, TS, Q>> {
+        let thread = self.thread.take()?;
+        let _ = self.commands.send(Command::Shutdown);
+        self.abort_handle.abort();
+        Some(thread.join().unwrap_or_else(|err| ::std::panic::resume_unwind(err)))
+    }
+}
+
+impl<Token, TS, Q> Drop for SchedulerHandle<Token, TS, Q> where TS: TimeSource + AbortableWait, Token: Clone, Q: TaskQueue<Token> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use steady_time_source::*;
+    use test_helpers::*;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    fn spawn() -> (SchedulerHandle<i32, SteadyTimeSource>, Batches<i32>) {
+        Scheduler::with_time_source(Duration::from_millis(10), SteadyTimeSource::new()).spawn()
+    }
+
+    // thread may be woken up past the time point of the batch on a busy machine
+    fn fired(schedule: Schedule<Fired<i32>>) -> Vec<i32> {
+        match schedule.tokens() {
+            Schedule::Current(tokens) | Schedule::Overrun(tokens) => tokens,
+            schedule => panic!("unexpected schedule: {:?}", schedule)
+        }
+    }
+
+    #[test]
+    fn deliver_batches() {
+        let (handle, batches) = spawn();
+        handle.every(Duration::from_millis(50), 1);
+
+        assert_eq!(fired(batches.recv().unwrap()), vec![1]);
+        assert_eq!(fired(batches.recv().unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn wake_on_earlier_task() {
+        let (handle, batches) = spawn();
+        handle.after(Duration::from_secs(20), 1);
+        sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        handle.after(Duration::from_millis(50), 2);
+        assert_eq!(fired(batches.recv_timeout(Duration::from_secs(5)).unwrap()), vec![2]);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn cancel_and_list() {
+        let (handle, _batches) = spawn();
+        let every = handle.every(Duration::from_secs(10), 1);
+        let after = handle.after(Duration::from_secs(20), 2);
+        assert_eq!(handle.try_after(Duration::from_secs(u64::MAX), 3), Err(SchedulerError::Overflow));
+
+        assert_eq!(handle.tasks().into_iter().map(|task| task.id).collect::<Vec<_>>(), vec![every, after]);
+        assert_eq!(handle.cancel_task(after), Some(2));
+        assert_eq!(handle.cancel_task(after), None);
+        assert_eq!(handle.with_scheduler(|scheduler| scheduler.len()), Ok(1));
+    }
+
+    #[test]
+    fn shutdown() {
+        let (handle, batches) = spawn();
+        handle.after(Duration::from_secs(20), 1);
+
+        let scheduler = handle.shutdown();
+        assert_eq!(scheduler.len(), 1);
+        assert!(batches.recv().is_err());
+    }
+
+    #[test]
+    fn stop_when_receiver_dropped() {
+        let (handle, batches) = spawn();
+        drop(batches);
+        handle.after(Duration::from_millis(10), 1);
+
+        let start = Instant::now();
+        while handle.try_after(Duration::from_secs(20), 2).is_ok() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(handle.try_every(Duration::from_secs(1), 3), Err(SchedulerError::Disconnected));
+        assert!(handle.tasks().is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use std::future::{self, Future, Ready};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use futures_core::Stream;
+
+use time_source::*;
+use task_queue::*;
+use scheduler::*;
+
+// Futures completing after given duration
+pub trait Timer<TS> where TS: TimeSource {
+    type Delay: Future<Output = ()> + Unpin;
+    fn delay(&mut self, time_source: &mut TS, duration: Duration) -> Self::Delay;
+}
+
+// Fast forwards the time source instead of waiting; for tests
+pub struct FastForwardTimer;
+
+impl<TS> Timer<TS> for FastForwardTimer where TS: TimeSource + FastForward {
+    type Delay = Ready<()>;
+
+    fn delay(&mut self, time_source: &mut TS, duration: Duration) -> Ready<()> {
+        time_source.fast_forward(duration);
+        future::ready(())
+    }
+}
+
+#[cfg(feature = "tokio-timer")]
+pub use self::tokio_timer::*;
+
+#[cfg(feature = "tokio-timer")]
+mod tokio_timer {
+    use std::pin::Pin;
+    use std::time::Duration;
+    use tokio::time::{sleep, Sleep};
+
+    use time_source::*;
+    use super::Timer;
+
+    // Must be polled within tokio runtime
+    pub struct TokioTimer;
+
+    impl<TS> Timer<TS> for TokioTimer where TS: TimeSource {
+        type Delay = Pin<Box<Sleep>>;
+
+        fn delay(&mut self, _time_source: &mut TS, duration: Duration) -> Pin<Box<Sleep>> {
+            Box::pin(sleep(duration))
+        }
+    }
+}
+
+// Batch of fired tasks yielded by SchedulerStream
+#[derive(Clone, Debug, PartialEq)]
+pub enum FiredBatch<Token> {
+    Current(Vec<Fired<Token>>),
+    // runs that were missed while the stream was not polled
+    Overrun(Vec<Fired<Token>>)
+}
+
+// Never ending stream of fired batches; pending while there are no tasks
+pub struct SchedulerStream<Token, TS, Q, T> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token>, T: Timer<TS> {
+    scheduler: Scheduler<Token, TS, Q>,
+    timer: T,
+    delay: Option<T::Delay>,
+    // task waiting for the schedule to change
+    waker: Option<Waker>
+}
+
+// delay is Unpin and no other field is ever pinned
+impl<Token, TS, Q, T> Unpin for SchedulerStream<Token, TS, Q, T> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token>, T: Timer<TS> {}
+
+impl<Token, TS, Q, T> SchedulerStream<Token, TS, Q, T> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token>, T: Timer<TS> {
+    // Access to scheduler to add or cancel tasks; task polling the stream is woken up to pick up the change
+    pub fn scheduler_mut(&mut self) -> &mut Scheduler<Token, TS, Q> {
+        // schedule may have changed so pending delay is no longer valid
+        self.delay = None;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        &mut self.scheduler
+    }
+
+    pub fn into_inner(self) -> Scheduler<Token, TS, Q> {
+        self.scheduler
+    }
+}
+
+impl<Token, TS, Q, T> Stream for SchedulerStream<Token, TS, Q, T> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token>, T: Timer<TS> {
+    type Item = Result<FiredBatch<Token>, WaitError<Fired<Token>>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.waker = Some(cx.waker().clone());
+
+        loop {
+            if let Some(ref mut delay) = this.delay {
+                match Pin::new(delay).poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(()) => ()
+                }
+            }
+            this.delay = None;
+
+            match this.scheduler.next() {
+                Some(Schedule::NextIn(duration)) => {
+                    this.delay = Some(this.timer.delay(&mut this.scheduler.time_source, duration));
+                },
+                Some(Schedule::Overrun(fired)) => return Poll::Ready(Some(Ok(FiredBatch::Overrun(fired)))),
+                Some(Schedule::Current(fired)) => return Poll::Ready(Some(Ok(FiredBatch::Current(fired)))),
+                Some(Schedule::ClockJump(offset)) => return Poll::Ready(Some(Err(WaitError::ClockJump(offset)))),
+                // woken up by scheduler_mut()
+                None => return Poll::Pending
+            }
+        }
+    }
+}
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    pub fn into_stream<T>(self, timer: T) -> SchedulerStream<Token, TS, Q, T> where T: Timer<TS> {
+        SchedulerStream {
+            scheduler: self,
+            timer,
+            delay: None,
+            waker: None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::time::Duration;
+    use futures_executor::block_on_stream;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn stream_fast_forward() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.after(Duration::from_secs(2), 1);
+        scheduler.every(Duration::from_secs(3), 2);
+        scheduler.after(Duration::from_secs(6), 3);
+
+        let mut stream = block_on_stream(scheduler.into_stream(FastForwardTimer));
+        assert_eq!(stream.next().tokens(), Some(Ok(Schedule::Current(vec![1]))));
+        assert_eq!(stream.next().tokens(), Some(Ok(Schedule::Current(vec![2]))));
+        assert_eq!(stream.next().tokens(), Some(Ok(Schedule::Current(vec![3, 2]))));
+    }
+
+    #[test]
+    fn stream_pending_when_empty() {
+        let mut stream = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new()).into_stream(FastForwardTimer);
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+
+        // adding a task wakes up the stream
+        stream.scheduler_mut().after(Duration::from_secs(1), 1);
+        assert!(flag.0.load(Ordering::SeqCst));
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(item) => assert_eq!(item.tokens(), Some(Ok(Schedule::Current(vec![1])))),
+            Poll::Pending => panic!("expected fired batch")
+        }
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn stream_overrun() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(3), 2);
+        scheduler.time_source.fast_forward(Duration::from_secs(2));
+
+        let mut stream = block_on_stream(scheduler.into_stream(FastForwardTimer));
+        assert_eq!(stream.next().tokens(), Some(Ok(Schedule::Overrun(vec![1]))));
+        assert_eq!(stream.next().tokens(), Some(Ok(Schedule::Current(vec![2]))));
+    }
+
+    #[cfg(feature = "tokio-timer")]
+    #[test]
+    fn stream_tokio_timer() {
+        use std::future::poll_fn;
+        use tokio::runtime::Builder;
+        use steady_time_source::*;
+
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(100), SteadyTimeSource::new());
+        scheduler.after(Duration::from_millis(100), 1);
+        scheduler.after(Duration::from_millis(200), 2);
+
+        let mut stream = scheduler.into_stream(TokioTimer);
+        let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+        let mut next = || runtime.block_on(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)));
+
+        assert_eq!(next().tokens(), Some(Ok(Schedule::Current(vec![1]))));
+        assert_eq!(next().tokens(), Some(Ok(Schedule::Current(vec![2]))));
+    }
+}
@@ -0,0 +1,199 @@
+use time_source::*;
+use scheduler::*;
+use task_queue::*;
+use task::*;
+use duration::*;
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    pub fn after_tagged<D>(&mut self, duration: D, token: Token, tags: &[&str]) -> TaskId where D: IntoDuration {
+        self.try_after_tagged(duration, token, tags).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_after_tagged<D>(&mut self, duration: D, token: Token, tags: &[&str]) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let id = self.try_after(duration, token)?;
+        for tag in tags {
+            self.tag(id, tag);
+        }
+        Ok(id)
+    }
+
+    pub fn every_tagged<D>(&mut self, duration: D, token: Token, tags: &[&str]) -> TaskId where D: IntoDuration {
+        self.try_every_tagged(duration, token, tags).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_every_tagged<D>(&mut self, duration: D, token: Token, tags: &[&str]) -> Result<TaskId, SchedulerError> where D: IntoDuration {
+        let id = self.try_every(duration, token)?;
+        for tag in tags {
+            self.tag(id, tag);
+        }
+        Ok(id)
+    }
+
+    // Add task to a group; tag is removed with the task when it is cancelled or does not run again.
+    // Returns false if there is no such task.
+    pub fn tag(&mut self, id: TaskId, tag: &str) -> bool {
+        if self.task(id).is_none() {
+            return false;
+        }
+
+        let tags = self.tags.entry(id).or_default();
+        if !tags.iter().any(|task_tag| task_tag == tag) {
+            tags.push(tag.to_owned());
+            self.tagged.entry(tag.to_owned()).or_default().insert(id);
+        }
+        true
+    }
+
+    // returns false if task was not tagged with given tag
+    pub fn untag_task(&mut self, id: TaskId, tag: &str) -> bool {
+        let removed = match self.tags.get_mut(&id) {
+            Some(tags) => match tags.iter().position(|task_tag| task_tag == tag) {
+                Some(position) => {
+                    tags.remove(position);
+                    true
+                },
+                None => false
+            },
+            None => false
+        };
+        if removed {
+            self.remove_tagged(id, tag);
+            if self.tags[&id].is_empty() {
+                self.tags.remove(&id);
+            }
+        }
+        removed
+    }
+
+    // tags of the task in order they were added
+    pub fn task_tags(&self, id: TaskId) -> Vec<&str> {
+        self.tags.get(&id)
+            .map(|tags| tags.iter().map(|tag| tag.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    // ids of tasks with given tag in order they were created
+    pub fn tagged(&self, tag: &str) -> Vec<TaskId> {
+        let mut ids: Vec<TaskId> = self.tagged.get(tag)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default();
+        ids.sort();
+        ids
+    }
+
+    // tokens of cancelled tasks in order they were created
+    pub fn cancel_tagged(&mut self, tag: &str) -> Vec<Token> {
+        self.tagged(tag).into_iter().filter_map(|id| self.cancel_task(id)).collect()
+    }
+
+    // returns number of tasks that were not paused already
+    pub fn pause_tagged(&mut self, tag: &str) -> usize {
+        let mut count = 0;
+        for id in self.tagged(tag) {
+            let paused = self.is_task_paused(id);
+            if self.pause_task(id) && !paused {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // returns number of tasks that were resumed
+    pub fn resume_tagged(&mut self, tag: &str) -> usize {
+        self.tagged(tag).into_iter().filter(|&id| self.resume_task(id)).count()
+    }
+
+    // drop task from tag index
+    pub(super) fn untag(&mut self, id: TaskId) {
+        if let Some(tags) = self.tags.remove(&id) {
+            for tag in tags {
+                self.remove_tagged(id, &tag);
+            }
+        }
+    }
+
+    fn remove_tagged(&mut self, id: TaskId, tag: &str) {
+        let empty = match self.tagged.get_mut(tag) {
+            Some(ids) => {
+                ids.remove(&id);
+                ids.is_empty()
+            },
+            None => false
+        };
+        if empty {
+            self.tagged.remove(tag);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_helpers::*;
+    use std::time::Duration;
+
+    #[test]
+    fn tag() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        let a = scheduler.every_tagged(Duration::from_secs(1), 1, &["tenant-a", "poll"]);
+        let b = scheduler.after_tagged(Duration::from_secs(1), 2, &["tenant-b", "poll"]);
+        let c = scheduler.after(Duration::from_secs(2), 3);
+        assert!(scheduler.tag(c, "tenant-a"));
+        assert!(scheduler.tag(c, "tenant-a"));
+        assert!(!scheduler.tag(TaskId(42), "tenant-a"));
+
+        assert_eq!(scheduler.tagged("tenant-a"), vec![a, c]);
+        assert_eq!(scheduler.tagged("poll"), vec![a, b]);
+        assert_eq!(scheduler.task_tags(a), vec!["tenant-a", "poll"]);
+        assert!(scheduler.tagged("tenant-c").is_empty());
+
+        assert!(scheduler.untag_task(a, "poll"));
+        assert!(!scheduler.untag_task(a, "poll"));
+        assert_eq!(scheduler.tagged("poll"), vec![b]);
+
+        // one-off task is untagged after it runs
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1, 2])));
+        assert!(scheduler.tagged("poll").is_empty());
+        assert!(scheduler.task_tags(b).is_empty());
+        assert_eq!(scheduler.tagged("tenant-a"), vec![a, c]);
+    }
+
+    #[test]
+    fn cancel_tagged() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.every_tagged(Duration::from_secs(1), 1, &["tenant-a"]);
+        scheduler.every_tagged(Duration::from_secs(1), 2, &["tenant-b"]);
+        let id = scheduler.after_tagged(Duration::from_secs(1), 3, &["tenant-a"]);
+        scheduler.pause_task(id);
+
+        assert_eq!(scheduler.cancel_tagged("tenant-a"), vec![1, 3]);
+        assert!(scheduler.cancel_tagged("tenant-a").is_empty());
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+
+        scheduler.cancel(&2);
+        assert!(scheduler.tagged("tenant-b").is_empty());
+    }
+
+    #[test]
+    fn pause_tagged() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
+
+        scheduler.every_tagged(Duration::from_secs(2), 1, &["tenant-a"]);
+        scheduler.every_tagged(Duration::from_secs(2), 2, &["tenant-b"]);
+        scheduler.after_tagged(Duration::from_secs(3), 3, &["tenant-a"]);
+
+        assert_eq!(scheduler.pause_tagged("tenant-a"), 2);
+        assert_eq!(scheduler.pause_tagged("tenant-a"), 0);
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![2])));
+
+        assert_eq!(scheduler.resume_tagged("tenant-a"), 2);
+        assert_eq!(scheduler.resume_tagged("tenant-a"), 0);
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.next().tokens(), Some(Schedule::Current(vec![1, 2])));
+    }
+}
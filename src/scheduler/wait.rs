@@ -1,21 +1,25 @@
 use std::fmt;
 use std::error::Error;
 use std::any::Any;
-use time::Duration;
+use std::time::Duration;
 
 use time_source::*;
 use scheduler::*;
+use task_queue::*;
+use duration::*;
 
 pub enum WaitError<Token> {
     Empty,
-    Overrun(Vec<Token>)
+    Overrun(Vec<Token>),
+    ClockJump(ClockOffset)
 }
 
 impl<Token> PartialEq for WaitError<Token> where Token: PartialEq<Token> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (&WaitError::Empty, &WaitError::Empty) => true,
-            (&WaitError::Overrun(ref tokens), &WaitError::Overrun(ref other_tokens)) => tokens == other_tokens,
+            (WaitError::Empty, WaitError::Empty) => true,
+            (WaitError::Overrun(tokens), WaitError::Overrun(other_tokens)) => tokens == other_tokens,
+            (WaitError::ClockJump(offset), WaitError::ClockJump(other_offset)) => offset == other_offset,
             _ => false
         }
     }
@@ -24,8 +28,9 @@ impl<Token> PartialEq for WaitError<Token> where Token: PartialEq<Token> {
 impl<Token> fmt::Debug for WaitError<Token> where Token: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &WaitError::Empty => write!(f, "WaitError::Empty"),
-            &WaitError::Overrun(ref tokens) => write!(f, "WaitError::Overrun({:?})", tokens)
+            WaitError::Empty => write!(f, "WaitError::Empty"),
+            WaitError::Overrun(tokens) => write!(f, "WaitError::Overrun({:?})", tokens),
+            WaitError::ClockJump(offset) => write!(f, "WaitError::ClockJump({:?})", offset)
         }
     }
 }
@@ -33,8 +38,9 @@ impl<Token> fmt::Debug for WaitError<Token> where Token: fmt::Debug {
 impl<Token> fmt::Display for WaitError<Token> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &WaitError::Empty => write!(f, "scheduler is empty"),
-            &WaitError::Overrun(ref tokens) => write!(f, "scheduler overrun {} tokens", tokens.len())
+            WaitError::Empty => write!(f, "scheduler is empty"),
+            WaitError::Overrun(tokens) => write!(f, "scheduler overrun {} tokens", tokens.len()),
+            WaitError::ClockJump(offset) => write!(f, "wall clock jumped {}", offset)
         }
     }
 }
@@ -48,15 +54,17 @@ impl<Token> Error for WaitError<Token> where Token: fmt::Debug + Any {
 pub enum WaitTimeoutError<Token> {
     Empty,
     Timeout,
-    Overrun(Vec<Token>)
+    Overrun(Vec<Token>),
+    ClockJump(ClockOffset)
 }
 
 impl<Token> PartialEq for WaitTimeoutError<Token> where Token: PartialEq<Token> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (&WaitTimeoutError::Empty, &WaitTimeoutError::Empty) => true,
-            (&WaitTimeoutError::Timeout, &WaitTimeoutError::Timeout) => true,
-            (&WaitTimeoutError::Overrun(ref tokens), &WaitTimeoutError::Overrun(ref other_tokens)) => tokens == other_tokens,
+            (WaitTimeoutError::Empty, WaitTimeoutError::Empty) => true,
+            (WaitTimeoutError::Timeout, WaitTimeoutError::Timeout) => true,
+            (WaitTimeoutError::Overrun(tokens), WaitTimeoutError::Overrun(other_tokens)) => tokens == other_tokens,
+            (WaitTimeoutError::ClockJump(offset), WaitTimeoutError::ClockJump(other_offset)) => offset == other_offset,
             _ => false
         }
     }
@@ -65,9 +73,10 @@ impl<Token> PartialEq for WaitTimeoutError<Token> where Token: PartialEq<Token>
 impl<Token> fmt::Debug for WaitTimeoutError<Token> where Token: fmt::Debug {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &WaitTimeoutError::Empty => write!(f, "WaitError::Empty"),
-            &WaitTimeoutError::Timeout => write!(f, "WaitError::Timeout"),
-            &WaitTimeoutError::Overrun(ref tokens) => write!(f, "WaitError::Overrun({:?})", tokens)
+            WaitTimeoutError::Empty => write!(f, "WaitError::Empty"),
+            WaitTimeoutError::Timeout => write!(f, "WaitError::Timeout"),
+            WaitTimeoutError::Overrun(tokens) => write!(f, "WaitError::Overrun({:?})", tokens),
+            WaitTimeoutError::ClockJump(offset) => write!(f, "WaitTimeoutError::ClockJump({:?})", offset)
         }
     }
 }
@@ -75,9 +84,10 @@ impl<Token> fmt::Debug for WaitTimeoutError<Token> where Token: fmt::Debug {
 impl<Token> fmt::Display for WaitTimeoutError<Token> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &WaitTimeoutError::Empty => write!(f, "scheduler is empty"),
-            &WaitTimeoutError::Timeout => write!(f, "timedout while waiting for tokens"),
-            &WaitTimeoutError::Overrun(ref tokens) => write!(f, "scheduler overrun {} tokens", tokens.len())
+            WaitTimeoutError::Empty => write!(f, "scheduler is empty"),
+            WaitTimeoutError::Timeout => write!(f, "timedout while waiting for tokens"),
+            WaitTimeoutError::Overrun(tokens) => write!(f, "scheduler overrun {} tokens", tokens.len()),
+            WaitTimeoutError::ClockJump(offset) => write!(f, "wall clock jumped {}", offset)
         }
     }
 }
@@ -88,8 +98,10 @@ impl<Token> Error for WaitTimeoutError<Token> where Token: fmt::Debug + Any {
     }
 }
 
-impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
-    pub fn wait(&mut self) -> Result<Vec<Token>, WaitError<Token>> where TS: Wait {
+pub type WaitResult<Token> = Result<Vec<Fired<Token>>, WaitError<Fired<Token>>>;
+
+impl<Token, TS, Q> Scheduler<Token, TS, Q> where TS: TimeSource, Token: Clone, Q: TaskQueue<Token> {
+    pub fn wait(&mut self) -> WaitResult<Token> where TS: Wait {
         match self.next() {
             Some(schedule) => match schedule {
                 Schedule::NextIn(duration) => {
@@ -99,6 +111,9 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
                 Schedule::Overrun(overrun_tokens) => {
                     Err(WaitError::Overrun(overrun_tokens))
                 },
+                Schedule::ClockJump(offset) => {
+                    Err(WaitError::ClockJump(offset))
+                },
                 Schedule::Current(tokens) => {
                     Ok(tokens)
                 }
@@ -107,7 +122,8 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
         }
     }
 
-    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Vec<Token>, WaitTimeoutError<Token>> where TS: Wait {
+    pub fn wait_timeout<D>(&mut self, timeout: D) -> Result<Vec<Fired<Token>>, WaitTimeoutError<Fired<Token>>> where TS: Wait, D: IntoDuration {
+        let timeout = timeout_duration(timeout);
         match self.next() {
             Some(schedule) => match schedule {
                 Schedule::NextIn(duration) => {
@@ -116,11 +132,14 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
                         return Err(WaitTimeoutError::Timeout);
                     }
                     self.time_source.wait(duration);
-                    self.wait_timeout(Duration::zero())
+                    self.wait_timeout(Duration::new(0, 0))
                 },
                 Schedule::Overrun(overrun_tokens) => {
                     Err(WaitTimeoutError::Overrun(overrun_tokens))
                 },
+                Schedule::ClockJump(offset) => {
+                    Err(WaitTimeoutError::ClockJump(offset))
+                },
                 Schedule::Current(tokens) => {
                     Ok(tokens)
                 }
@@ -129,7 +148,7 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
         }
     }
 
-    pub fn try(&mut self) -> Option<Result<Vec<Token>, WaitError<Token>>> {
+    pub fn try(&mut self) -> Option<WaitResult<Token>> {
         match self.next() {
             Some(schedule) => match schedule {
                 Schedule::NextIn(_) => {
@@ -138,6 +157,9 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
                 Schedule::Overrun(overrun_tokens) => {
                     Some(Err(WaitError::Overrun(overrun_tokens)))
                 },
+                Schedule::ClockJump(offset) => {
+                    Some(Err(WaitError::ClockJump(offset)))
+                },
                 Schedule::Current(tokens) => {
                     Some(Ok(tokens))
                 }
@@ -150,37 +172,35 @@ impl<Token, TS> Scheduler<Token, TS> where TS: TimeSource, Token: Clone {
 #[cfg(test)]
 mod test {
     use super::*;
-    use time_source::*;
     use steady_time_source::*;
-    use scheduler::*;
     use test_helpers::*;
-    use time::Duration;
+    use std::time::Duration;
 
     #[test]
     fn wait() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        assert_eq!(scheduler.wait(), Ok(vec![0]));
-        assert_eq!(scheduler.wait(), Ok(vec![1]));
-        assert_eq!(scheduler.wait(), Ok(vec![2]));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![1]));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![2]));
     }
 
     #[test]
     fn wait_opeque_token() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), Zero);
-        scheduler.after(Duration::seconds(1), One);
-        scheduler.after(Duration::seconds(2), Two);
+        scheduler.after(Duration::from_secs(0), Zero);
+        scheduler.after(Duration::from_secs(1), One);
+        scheduler.after(Duration::from_secs(2), Two);
 
         match scheduler.wait() {
             Ok(tokens) => {
                 assert_eq!(tokens.len(), 1);
-                tokens.first().unwrap().expect(Zero)
+                tokens.first().unwrap().token.expect(Zero)
             }
             _ => panic!("expected Ok")
         }
@@ -188,55 +208,65 @@ mod test {
 
     #[test]
     fn wait_with_empty() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
-        assert_eq!(scheduler.wait(), Err(WaitError::Empty));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
+        assert_eq!(scheduler.wait().tokens(), Err(WaitError::Empty));
 
-        scheduler.after(Duration::seconds(0), 0);
-        assert_eq!(scheduler.wait(), Ok(vec![0]));
-        assert_eq!(scheduler.wait(), Err(WaitError::Empty));
+        scheduler.after(Duration::from_secs(0), 0);
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.wait().tokens(), Err(WaitError::Empty));
     }
 
     #[test]
     fn wait_with_overrun() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        scheduler.fast_forward(Duration::seconds(2));
-        assert_eq!(scheduler.wait(), Err(WaitError::Overrun(vec![0, 1])));
-        assert_eq!(scheduler.wait(), Ok(vec![2]));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.wait().tokens(), Err(WaitError::Overrun(vec![0, 1])));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![2]));
     }
 
     #[test]
     fn wait_timeout_with_timeout() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        assert_eq!(scheduler.wait_timeout(Duration::seconds(2)), Ok(vec![0]));
-        assert_eq!(scheduler.wait_timeout(Duration::milliseconds(500)), Err(WaitTimeoutError::Timeout));
+        assert_eq!(scheduler.wait_timeout(Duration::from_secs(2)).tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.wait_timeout(Duration::from_millis(500)).tokens(), Err(WaitTimeoutError::Timeout));
+    }
+
+    #[cfg(feature = "legacy-time")]
+    #[test]
+    fn wait_timeout_negative() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
+        scheduler.after(Duration::from_secs(1), 1);
+
+        assert_eq!(scheduler.wait_timeout(::time::Duration::seconds(-1)).tokens(), Err(WaitTimeoutError::Timeout));
+        assert_eq!(scheduler.time_source.now(), Duration::new(0, 0));
     }
 
     #[test]
     fn wait_timeout_with_timeout_opeque_token() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
 
-        scheduler.after(Duration::seconds(0), Zero);
-        scheduler.after(Duration::seconds(1), One);
-        scheduler.after(Duration::seconds(2), Two);
+        scheduler.after(Duration::from_secs(0), Zero);
+        scheduler.after(Duration::from_secs(1), One);
+        scheduler.after(Duration::from_secs(2), Two);
 
-        match scheduler.wait_timeout(Duration::seconds(2)) {
+        match scheduler.wait_timeout(Duration::from_secs(2)) {
             Ok(tokens) => {
                 assert_eq!(tokens.len(), 1);
-                tokens.first().unwrap().expect(Zero)
+                tokens.first().unwrap().token.expect(Zero)
             }
             _ => panic!("expected Ok")
         }
-        match scheduler.wait_timeout(Duration::milliseconds(500)) {
+        match scheduler.wait_timeout(Duration::from_millis(500)) {
             Err(WaitTimeoutError::Timeout) => (),
             _ => panic!("expected Err(WaitTimeoutError::Timeout)")
         }
@@ -244,58 +274,81 @@ mod test {
 
     #[test]
     fn wait_steady_time_source() {
-        let mut scheduler = Scheduler::with_time_source(Duration::milliseconds(100), SteadyTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_millis(100), SteadyTimeSource::new());
 
-        scheduler.after(Duration::milliseconds(0), 0);
-        scheduler.after(Duration::milliseconds(100), 1);
-        scheduler.after(Duration::milliseconds(200), 2);
+        scheduler.after(Duration::from_millis(0), 0);
+        scheduler.after(Duration::from_millis(100), 1);
+        scheduler.after(Duration::from_millis(200), 2);
 
-        assert_eq!(scheduler.wait(), Ok(vec![0]));
-        assert_eq!(scheduler.wait(), Ok(vec![1]));
-        assert_eq!(scheduler.wait(), Ok(vec![2]));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![0]));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![1]));
+        assert_eq!(scheduler.wait().tokens(), Ok(vec![2]));
     }
 
     #[test]
     fn try() {
-        let mut scheduler = Scheduler::with_time_source(Duration::nanoseconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_nanos(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        assert_eq!(scheduler.try(), Some(Ok(vec![0])));
-        assert_eq!(scheduler.try(), None);
-        assert_eq!(scheduler.try(), None);
+        assert_eq!(scheduler.try().tokens(), Some(Ok(vec![0])));
+        assert_eq!(scheduler.try().tokens(), None);
+        assert_eq!(scheduler.try().tokens(), None);
 
-        scheduler.fast_forward(Duration::seconds(1));
-        assert_eq!(scheduler.try(), Some(Ok(vec![1])));
-        assert_eq!(scheduler.try(), None);
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.try().tokens(), Some(Ok(vec![1])));
+        assert_eq!(scheduler.try().tokens(), None);
 
-        scheduler.fast_forward(Duration::seconds(1));
-        assert_eq!(scheduler.try(), Some(Ok(vec![2])));
-        assert_eq!(scheduler.try(), Some(Err(WaitError::Empty)));
+        scheduler.fast_forward(Duration::from_secs(1));
+        assert_eq!(scheduler.try().tokens(), Some(Ok(vec![2])));
+        assert_eq!(scheduler.try().tokens(), Some(Err(WaitError::Empty)));
     }
 
     #[test]
     fn try_with_empty() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSourceWait::new());
-        assert_eq!(scheduler.try(), Some(Err(WaitError::Empty)));
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
+        assert_eq!(scheduler.try().tokens(), Some(Err(WaitError::Empty)));
 
-        scheduler.after(Duration::seconds(0), 0);
-        assert_eq!(scheduler.try(), Some(Ok(vec![0])));
-        assert_eq!(scheduler.try(), Some(Err(WaitError::Empty)));
+        scheduler.after(Duration::from_secs(0), 0);
+        assert_eq!(scheduler.try().tokens(), Some(Ok(vec![0])));
+        assert_eq!(scheduler.try().tokens(), Some(Err(WaitError::Empty)));
     }
 
     #[test]
     fn try_with_overrun() {
-        let mut scheduler = Scheduler::with_time_source(Duration::seconds(1), MockTimeSource::new());
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSource::new());
 
-        scheduler.after(Duration::seconds(0), 0);
-        scheduler.after(Duration::seconds(1), 1);
-        scheduler.after(Duration::seconds(2), 2);
+        scheduler.after(Duration::from_secs(0), 0);
+        scheduler.after(Duration::from_secs(1), 1);
+        scheduler.after(Duration::from_secs(2), 2);
 
-        scheduler.fast_forward(Duration::seconds(2));
-        assert_eq!(scheduler.try(), Some(Err(WaitError::Overrun(vec![0, 1]))));
-        assert_eq!(scheduler.try(), Some(Ok(vec![2])));
+        scheduler.fast_forward(Duration::from_secs(2));
+        assert_eq!(scheduler.try().tokens(), Some(Err(WaitError::Overrun(vec![0, 1]))));
+        assert_eq!(scheduler.try().tokens(), Some(Ok(vec![2])));
+    }
+
+    #[test]
+    fn wait_fired() {
+        let mut scheduler = Scheduler::with_time_source(Duration::from_secs(1), MockTimeSourceWait::new());
+
+        scheduler.every(Duration::from_secs(2), 0);
+
+        let fired = scheduler.wait().unwrap();
+        assert_eq!(fired[0].scheduled, Duration::from_secs(2));
+        assert_eq!(fired[0].fired, Duration::from_secs(2));
+        assert_eq!(fired[0].lateness, Duration::new(0, 0));
+        assert_eq!(fired[0].iteration, 0);
+
+        scheduler.fast_forward(Duration::from_secs(3));
+        match scheduler.wait() {
+            Err(WaitError::Overrun(fired)) => {
+                assert_eq!(fired[0].scheduled, Duration::from_secs(4));
+                assert_eq!(fired[0].lateness, Duration::from_secs(1));
+                assert_eq!(fired[0].iteration, 1);
+            },
+            result => panic!("expected Err(WaitError::Overrun) but got {:?}", result)
+        }
     }
 }
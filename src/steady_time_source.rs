@@ -1,52 +1,95 @@
 use std::sync::{Mutex, Arc};
 use std::thread::{self, Thread, sleep};
-use std::time::Duration as StdDuration;
-use time::{SteadyTime, Duration};
+use std::time::{Duration, Instant, SystemTime};
 
 use time_source::*;
 
+struct AbortState {
+    // number of abortable waits started so far
+    generation: u64,
+    // thread blocked in abortable wait of current generation
+    waiter: Option<Thread>,
+    // generation of the wait to abort: the one in progress or the next one
+    aborted: Option<u64>
+}
+
+// copies share abort handles
+#[derive(Clone)]
 pub struct SteadyTimeSource {
-    offset: SteadyTime,
-    abort: Arc<Mutex<bool>>
+    offset: Instant,
+    // time source was fast forwarded by
+    forwarded: Duration,
+    // wall clock time at offset; later changes of system clock are not followed, see SystemTimeSource
+    epoch: SystemTime,
+    abort: Arc<Mutex<AbortState>>
+}
+
+impl Default for SteadyTimeSource {
+    fn default() -> SteadyTimeSource {
+        SteadyTimeSource::new()
+    }
 }
 
 impl SteadyTimeSource {
     pub fn new() -> SteadyTimeSource {
         SteadyTimeSource {
-            offset: SteadyTime::now(),
-            abort: Arc::new(Mutex::new(false))
+            offset: Instant::now(),
+            forwarded: Duration::new(0, 0),
+            epoch: SystemTime::now(),
+            abort: Arc::new(Mutex::new(AbortState {
+                generation: 0,
+                waiter: None,
+                aborted: None
+            }))
         }
     }
 }
 
 impl TimeSource for SteadyTimeSource {
     fn now(&self) -> Duration {
-        SteadyTime::now() - self.offset
+        Instant::now() - self.offset + self.forwarded
+    }
+}
+
+impl WallClock for SteadyTimeSource {
+    fn epoch(&self) -> SystemTime {
+        self.epoch
+    }
+}
+
+impl InstantClock for SteadyTimeSource {
+    fn time_at(&self, instant: Instant) -> Option<Duration> {
+        match instant.checked_duration_since(self.offset) {
+            Some(elapsed) => elapsed.checked_add(self.forwarded),
+            None => self.forwarded.checked_sub(self.offset - instant)
+        }
     }
 }
 
 impl Wait for SteadyTimeSource {
     fn wait(&mut self, duration: Duration) {
-        sleep(StdDuration::new(
-            duration.num_seconds() as u64,
-            (duration.num_nanoseconds().expect("sleep duration too large") - duration.num_seconds() * 1_000_000_000) as u32
-        ));
+        sleep(duration);
     }
 }
 
+// Aborts wait in progress or the next wait if there is none
+#[derive(Clone)]
 pub struct SteadyTimeSourceAbortHandle {
-    waiter_thread: Thread,
-    abort: Arc<Mutex<bool>>
+    abort: Arc<Mutex<AbortState>>
 }
 
 impl Abort for SteadyTimeSourceAbortHandle {
     fn abort(&self) {
-        {
-            let mut abort = self.abort.lock().unwrap();
-            *abort = true;
+        let mut state = self.abort.lock().unwrap();
+        let generation = if state.waiter.is_some() { state.generation } else { state.generation + 1 };
+        state.aborted = Some(generation);
+        if let Some(ref waiter) = state.waiter {
+            waiter.unpark();
         }
+    }
 
-        self.waiter_thread.unpark();
+    fn reset(&self) {
+        self.abort.lock().unwrap().aborted = None;
     }
 }
 
@@ -55,38 +98,58 @@ impl AbortableWait for SteadyTimeSource {
 
     fn abort_handle(&self) -> Self::AbortHandle {
         SteadyTimeSourceAbortHandle {
-            waiter_thread: thread::current(),
             abort: self.abort.clone()
         }
     }
 
     fn abortable_wait(&mut self, duration: Duration) -> Result<(), WaitAbortedError> {
-        //TODO: this can spuriously return
-        thread::park_timeout(StdDuration::new(
-            duration.num_seconds() as u64,
-            (duration.num_nanoseconds().expect("sleep duration too large") - duration.num_seconds() * 1_000_000_000) as u32
-        ));
-        if *self.abort.lock().unwrap() {
-            Err(WaitAbortedError)
-        } else {
-            Ok(())
+        // None if deadline is too far to be represented; wait until aborted then
+        let deadline = Instant::now().checked_add(duration);
+        let generation = {
+            let mut state = self.abort.lock().unwrap();
+            state.generation += 1;
+            state.waiter = Some(thread::current());
+            state.generation
+        };
+
+        loop {
+            {
+                let mut state = self.abort.lock().unwrap();
+                if state.aborted == Some(generation) {
+                    state.aborted = None;
+                    state.waiter = None;
+                    return Err(WaitAbortedError);
+                }
+                match deadline {
+                    Some(deadline) if Instant::now() >= deadline => {
+                        state.waiter = None;
+                        return Ok(());
+                    },
+                    _ => ()
+                }
+            }
+
+            // park can return spuriously or because of unpark meant for something else so deadline
+            // and abort are checked again; unpark done before parking makes it return right away
+            match deadline {
+                Some(deadline) => thread::park_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => thread::park()
+            }
         }
     }
 }
 
 impl FastForward for SteadyTimeSource {
     fn fast_forward(&mut self, duration: Duration) {
-        assert!(duration > Duration::seconds(0), "fast_forward must be positive Duration");
-        self.offset = self.offset - duration;
+        self.forwarded += duration;
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use time_source::*;
     use std::thread::spawn;
-    use time::Duration;
+    use std::time::Duration;
 
     #[test]
     fn abortable_wait_early_abort() {
@@ -97,7 +160,7 @@ mod test {
             abort_handle.abort();
         });
 
-        assert_eq!(sts.abortable_wait(Duration::seconds(2)), Err(WaitAbortedError));
+        assert_eq!(sts.abortable_wait(Duration::from_secs(2)), Err(WaitAbortedError));
     }
 
     #[test]
@@ -106,7 +169,77 @@ mod test {
 
         let _ = sts.abort_handle();
 
-        assert_eq!(sts.abortable_wait(Duration::seconds(1)), Ok(()));
+        assert_eq!(sts.abortable_wait(Duration::from_secs(1)), Ok(()));
+    }
+
+    #[test]
+    fn abort_affects_one_wait() {
+        let mut sts = SteadyTimeSource::new();
+        let abort_handle = sts.abort_handle();
+
+        abort_handle.abort();
+        abort_handle.abort();
+        assert_eq!(sts.abortable_wait(Duration::from_secs(2)), Err(WaitAbortedError));
+        assert_eq!(sts.abortable_wait(Duration::from_millis(10)), Ok(()));
+    }
+
+    #[test]
+    fn abort_reset() {
+        let mut sts = SteadyTimeSource::new();
+        let abort_handle = sts.abort_handle();
+
+        abort_handle.abort();
+        abort_handle.reset();
+        assert_eq!(sts.abortable_wait(Duration::from_millis(10)), Ok(()));
+    }
+
+    #[test]
+    fn abort_waiter_on_other_thread() {
+        let mut sts = SteadyTimeSource::new();
+        // handle is made on this thread but wait happens on another one
+        let abort_handle = sts.abort_handle();
+
+        let start = Instant::now();
+        let handle = spawn(move || (sts.abortable_wait(Duration::from_secs(10)), sts));
+        sleep(Duration::from_millis(50));
+        abort_handle.abort();
+
+        let (result, mut sts) = handle.join().unwrap();
+        assert_eq!(result, Err(WaitAbortedError));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // abort after the wait has finished applies to the next wait only
+        abort_handle.abort();
+        assert_eq!(sts.abortable_wait(Duration::from_secs(10)), Err(WaitAbortedError));
+        assert_eq!(sts.abortable_wait(Duration::from_millis(10)), Ok(()));
+    }
+
+    #[test]
+    fn abortable_wait_ignores_spurious_wakeups() {
+        let mut sts = SteadyTimeSource::new();
+
+        let start = Instant::now();
+        let handle = spawn(move || sts.abortable_wait(Duration::from_millis(200)));
+        for _ in 0..10 {
+            handle.thread().unpark();
+            sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(handle.join().unwrap(), Ok(()));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn time_at() {
+        let mut sts = SteadyTimeSource::new();
+        let instant = Instant::now();
+        let now = sts.now();
+        assert!(sts.time_at(instant).unwrap() <= now);
+        assert_eq!(sts.time_at(instant - Duration::from_secs(60)), None);
+
+        sts.fast_forward(Duration::from_secs(120));
+        assert!(sts.time_at(instant).unwrap() >= Duration::from_secs(120));
+        assert!(sts.time_at(instant - Duration::from_secs(60)).unwrap() >= Duration::from_secs(60));
     }
 
     #[test]
@@ -114,9 +247,13 @@ mod test {
         let mut sts = SteadyTimeSource::new();
 
         let now = sts.now();
-        assert!(now + Duration::seconds(1) > sts.now());
+        assert!(now + Duration::from_secs(1) > sts.now());
+
+        sts.fast_forward(Duration::from_secs(1));
+        assert!(now + Duration::from_secs(1) <= sts.now());
 
-        sts.fast_forward(Duration::seconds(1));
-        assert!(now + Duration::seconds(1) <= sts.now());
+        // further than time since system start
+        sts.fast_forward(Duration::from_secs(100 * 365 * 24 * 60 * 60));
+        assert!(now + Duration::from_secs(100 * 365 * 24 * 60 * 60) <= sts.now());
     }
 }
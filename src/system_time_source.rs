@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use time_source::*;
+use steady_time_source::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockJumpPolicy {
+    // move pending wall clock anchored tasks so they still fire at the same wall clock time
+    Reanchor,
+    // report the jump to the caller who can re-anchor the tasks with Scheduler::reanchor
+    Report
+}
+
+// Monotonic like SteadyTimeSource but tracks wall clock to detect system clock steps
+#[derive(Clone)]
+pub struct SystemTimeSource {
+    steady: SteadyTimeSource,
+    epoch: SystemTime,
+    tolerance: Duration,
+    policy: ClockJumpPolicy
+}
+
+impl Default for SystemTimeSource {
+    fn default() -> SystemTimeSource {
+        SystemTimeSource::new()
+    }
+}
+
+impl SystemTimeSource {
+    pub fn new() -> SystemTimeSource {
+        SystemTimeSource::with_policy(ClockJumpPolicy::Reanchor, Duration::from_secs(1))
+    }
+
+    // clock differences up to tolerance are not considered a jump
+    pub fn with_policy(policy: ClockJumpPolicy, tolerance: Duration) -> SystemTimeSource {
+        let steady = SteadyTimeSource::new();
+        let epoch = SystemTime::now() - steady.now();
+        SystemTimeSource {
+            steady,
+            epoch,
+            tolerance,
+            policy
+        }
+    }
+}
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> Duration {
+        self.steady.now()
+    }
+
+    fn clock_jump(&mut self) -> Option<ClockJump> {
+        let drift = ClockOffset::between(self.epoch + self.steady.now(), SystemTime::now());
+        if drift.duration() <= self.tolerance {
+            return None;
+        }
+
+        self.epoch = drift.apply(self.epoch);
+        Some(match self.policy {
+            ClockJumpPolicy::Reanchor => ClockJump::Reanchor(drift),
+            ClockJumpPolicy::Report => ClockJump::Report(drift)
+        })
+    }
+}
+
+impl WallClock for SystemTimeSource {
+    fn epoch(&self) -> SystemTime {
+        self.epoch
+    }
+}
+
+impl InstantClock for SystemTimeSource {
+    fn time_at(&self, instant: Instant) -> Option<Duration> {
+        self.steady.time_at(instant)
+    }
+}
+
+impl Wait for SystemTimeSource {
+    fn wait(&mut self, duration: Duration) {
+        self.steady.wait(duration)
+    }
+}
+
+impl AbortableWait for SystemTimeSource {
+    type AbortHandle = SteadyTimeSourceAbortHandle;
+
+    fn abort_handle(&self) -> Self::AbortHandle {
+        self.steady.abort_handle()
+    }
+
+    fn abortable_wait(&mut self, duration: Duration) -> Result<(), WaitAbortedError> {
+        self.steady.abortable_wait(duration)
+    }
+}
+
+impl FastForward for SystemTimeSource {
+    fn fast_forward(&mut self, duration: Duration) {
+        self.steady.fast_forward(duration);
+        // keep epoch + now() at current wall clock time so this is not taken for a clock jump
+        self.epoch -= duration;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn epoch() {
+        let sts = SystemTimeSource::new();
+
+        let drift = ClockOffset::between(sts.epoch() + sts.now(), SystemTime::now());
+        assert!(drift.duration() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn no_clock_jump() {
+        let mut sts = SystemTimeSource::new();
+        assert_eq!(sts.clock_jump(), None);
+
+        sts.fast_forward(Duration::from_secs(60 * 60));
+        assert_eq!(sts.clock_jump(), None);
+    }
+
+    #[test]
+    fn clock_jump_reanchor() {
+        let mut sts = SystemTimeSource::with_policy(ClockJumpPolicy::Reanchor, Duration::from_secs(1));
+        let epoch = sts.epoch();
+
+        // as if system clock was stepped forward by an hour
+        sts.epoch -= Duration::from_secs(60 * 60);
+
+        match sts.clock_jump() {
+            Some(ClockJump::Reanchor(ClockOffset::Forward(offset))) => assert!(offset > Duration::from_secs(59 * 60) && offset < Duration::from_secs(61 * 60)),
+            jump => panic!("expected ClockJump::Reanchor but got {:?}", jump)
+        }
+        assert!(ClockOffset::between(epoch, sts.epoch()).duration() < Duration::from_secs(1));
+        assert_eq!(sts.clock_jump(), None);
+    }
+
+    #[test]
+    fn clock_jump_report() {
+        let mut sts = SystemTimeSource::with_policy(ClockJumpPolicy::Report, Duration::from_secs(1));
+
+        // as if system clock was stepped back by a minute
+        sts.epoch += Duration::from_secs(60);
+
+        match sts.clock_jump() {
+            Some(ClockJump::Report(ClockOffset::Backward(offset))) => assert!(offset > Duration::from_secs(59) && offset < Duration::from_secs(61)),
+            jump => panic!("expected ClockJump::Report but got {:?}", jump)
+        }
+        assert_eq!(sts.clock_jump(), None);
+    }
+}
@@ -1,54 +1,339 @@
-use time::Duration;
+use std::time::{Duration, SystemTime};
+
+use cron::CronSchedule;
+use backoff::Backoff;
+use rng::JitterRng;
+use scheduler::SchedulerError;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TaskId(pub u64);
 
 #[derive(Clone)]
 pub struct Task<Token> where Token: Clone {
+    pub id: TaskId,
     pub interval: Duration,
-    pub run_offset: Duration,
+    // when the run is due without jitter
+    pub due: Duration,
     pub token: Token,
-    pub bond: TaskBond
+    pub bond: TaskBond,
+    pub overrun_policy: OverrunPolicy,
+    pub run_jitter: Option<RunJitter>,
+    // tasks with higher priority come first in a batch of fired tasks
+    pub priority: i32,
+    // number of runs before this one
+    pub iteration: u64
 }
 
 #[derive(Clone, Debug)]
 pub enum TaskBond {
     OneOff,
-    Perpetual
+    Perpetual,
+    // runs given number of times in total
+    Repeat(u64),
+    // runs until given time; no run is scheduled after it
+    Until(Duration),
+    // next schedule is computed from the expression; schedules are relative to given wall clock epoch
+    Cron(CronSchedule, SystemTime),
+    // runs once at given wall clock time
+    At(SystemTime),
+    // runs again only after previous run was acknowledged; interval is measured from the acknowledgement
+    FixedDelay,
+    // delay grows with each run; current delay before jitter and generator used to randomize it
+    Backoff(Backoff, Duration, JitterRng)
 }
 
-impl<Token> Task<Token> where Token: Clone {
-    pub fn new(interval: Duration, run_offset: Duration, bond: TaskBond, token: Token) -> Task<Token> {
-        assert!(interval >= Duration::seconds(0), "negative interval would make schedule go back in time!");
-        Task {
-            interval: interval,
-            run_offset: run_offset,
-            bond: bond,
-            token: token
+// What to do with runs of repeating task that were missed while scheduler was not polled
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OverrunPolicy {
+    // report every missed run
+    CatchUp,
+    // report missed runs once and continue with next run that is not overdue
+    Coalesce,
+    // drop missed runs silently and continue with next run that is not overdue
+    Skip,
+    // report missed runs once and schedule next run relative to now
+    Delay
+}
+
+// How to randomize when runs of a task are due
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jitter {
+    // delay pending run by random time up to given duration; following runs keep the shifted phase
+    Splay(Duration),
+    // delay each run by random time up to given duration from its regular schedule
+    PerRun(Duration)
+}
+
+// When runs of a task with changed interval are due
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntervalChange {
+    // keep when next run is due and apply new interval to following runs
+    KeepNextRun,
+    // next run is due new interval after the last run (or after the task was scheduled if it did not run yet)
+    FromLastRun
+}
+
+// Random delay of current run of a task
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunJitter {
+    pub max: Duration,
+    pub offset: Duration,
+    pub rng: JitterRng
+}
+
+impl RunJitter {
+    pub fn new(max: Duration, mut rng: JitterRng) -> RunJitter {
+        RunJitter {
+            max,
+            offset: rng.duration(max),
+            rng
         }
     }
 
-    pub fn next(self) -> Task<Token> {
-        Task {
-            run_offset: self.run_offset + self.interval,
+    pub fn next(mut self) -> RunJitter {
+        self.offset = self.rng.duration(self.max);
+        self
+    }
+}
+
+impl<Token> Task<Token> where Token: Clone {
+    // first run is due interval after start
+    pub fn new(id: TaskId, interval: Duration, start: Duration, bond: TaskBond, token: Token) -> Result<Task<Token>, SchedulerError> {
+        Ok(Task {
+            id,
+            interval,
+            due: start.checked_add(interval).ok_or(SchedulerError::Overflow)?,
+            bond,
+            token,
+            overrun_policy: OverrunPolicy::CatchUp,
+            run_jitter: None,
+            priority: 0,
+            iteration: 0
+        })
+    }
+
+    // None if next run is too far in the future to be represented
+    pub fn next(self) -> Option<Task<Token>> {
+        Some(Task {
+            due: self.due.checked_add(self.interval)?,
+            run_jitter: self.run_jitter.map(RunJitter::next),
+            iteration: self.iteration + 1,
             .. self
-        }
+        })
     }
 
+    // Task for the next run according to its bond or None if it should not run again
+    pub fn reschedule(self) -> Option<Task<Token>> {
+        // jitter of the run does not move following runs
+        let regular_schedule = self.regular_schedule();
+        self.reschedule_from(regular_schedule)
+    }
+
+    // Task for the next run counted from given time of the last run or None if it should not run again;
+    // task with next run too far in the future to be represented does not run again either
+    pub fn reschedule_from(self, last_run: Duration) -> Option<Task<Token>> {
+        let (interval, bond) = match self.bond {
+            TaskBond::OneOff | TaskBond::At(_) => return None,
+            TaskBond::Perpetual => (self.interval, TaskBond::Perpetual),
+            TaskBond::FixedDelay => (self.interval, TaskBond::FixedDelay),
+            TaskBond::Backoff(backoff, delay, mut rng) => {
+                let delay = backoff.next_delay(delay);
+                (backoff.jittered(delay, &mut rng), TaskBond::Backoff(backoff, delay, rng))
+            },
+            TaskBond::Repeat(count) => {
+                if self.iteration + 1 >= count {
+                    return None;
+                }
+                (self.interval, TaskBond::Repeat(count))
+            },
+            TaskBond::Until(deadline) => {
+                match last_run.checked_add(self.interval) {
+                    Some(next) if next <= deadline => (),
+                    _ => return None
+                }
+                (self.interval, TaskBond::Until(deadline))
+            },
+            TaskBond::Cron(schedule, epoch) => {
+                let last = epoch.checked_add(last_run)?;
+                match schedule.next_after(last) {
+                    Some(next) => (next.duration_since(last).unwrap_or_default(), TaskBond::Cron(schedule, epoch)),
+                    None => return None
+                }
+            }
+        };
+
+        Some(Task {
+            due: last_run.checked_add(interval)?,
+            interval,
+            bond,
+            run_jitter: self.run_jitter.map(RunJitter::next),
+            iteration: self.iteration + 1,
+            .. self
+        })
+    }
+
+    pub fn with_interval(self, interval: Duration, change: IntervalChange) -> Result<Task<Token>, SchedulerError> {
+        let due = match change {
+            IntervalChange::KeepNextRun => self.due,
+            // task that did not run yet counts from time it was scheduled at
+            IntervalChange::FromLastRun => self.due.checked_sub(self.interval).unwrap_or_default()
+                .checked_add(interval).ok_or(SchedulerError::Overflow)?
+        };
+        Ok(Task {
+            due,
+            interval,
+            .. self
+        })
+    }
+
+    // delay the task by random time up to jitter max drawn from given generator
+    pub fn with_jitter(self, jitter: Jitter, mut rng: JitterRng) -> Result<Task<Token>, SchedulerError> {
+        Ok(match jitter {
+            Jitter::Splay(max) => Task {
+                due: self.due.checked_add(rng.duration(max)).ok_or(SchedulerError::Overflow)?,
+                .. self
+            },
+            Jitter::PerRun(max) => Task {
+                run_jitter: Some(RunJitter::new(max, rng)),
+                .. self
+            }
+        })
+    }
+
+    // when the run is due; saturates as such run cannot be scheduled anyway
     pub fn schedule(&self) -> Duration {
-        self.run_offset + self.interval
+        match self.run_jitter {
+            Some(ref run_jitter) => self.regular_schedule().checked_add(run_jitter.offset).unwrap_or(Duration::MAX),
+            None => self.regular_schedule()
+        }
+    }
+
+    // when the run would be due without jitter
+    pub fn regular_schedule(&self) -> Duration {
+        self.due
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use time::Duration;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn next_schedule() {
-        let now = Duration::seconds(0);
-        let interval = Duration::seconds(1);
-        let task = Task::new(interval, now, TaskBond::OneOff, 42);
+        let now = Duration::from_secs(0);
+        let interval = Duration::from_secs(1);
+        let task = Task::new(TaskId(0), interval, now, TaskBond::OneOff, 42).unwrap();
 
         assert_eq!(task.schedule(), now + interval);
-        assert_eq!(task.next().next().schedule(), now + interval * 3);
+        assert_eq!(task.next().unwrap().next().unwrap().schedule(), now + interval * 3);
+    }
+
+    #[test]
+    fn iteration() {
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::from_secs(0), TaskBond::Perpetual, 42).unwrap();
+        assert_eq!(task.iteration, 0);
+        assert_eq!(task.next().unwrap().reschedule().unwrap().iteration, 2);
+    }
+
+    #[test]
+    fn next_keeps_id() {
+        let task = Task::new(TaskId(7), Duration::from_secs(1), Duration::from_secs(0), TaskBond::Perpetual, 42).unwrap();
+
+        assert_eq!(task.next().unwrap().id, TaskId(7));
+    }
+
+    #[test]
+    fn reschedule() {
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::from_secs(0), TaskBond::OneOff, 42).unwrap();
+        assert!(task.reschedule().is_none());
+
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::from_secs(0), TaskBond::Perpetual, 42).unwrap();
+        assert_eq!(task.reschedule().unwrap().schedule(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn reschedule_repeat() {
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::from_secs(0), TaskBond::Repeat(3), 42).unwrap();
+        let task = task.reschedule().unwrap().reschedule().unwrap();
+        assert_eq!(task.schedule(), Duration::from_secs(3));
+        assert!(task.reschedule().is_none());
+
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::from_secs(0), TaskBond::Until(Duration::from_secs(2)), 42).unwrap();
+        let task = task.reschedule().unwrap();
+        assert_eq!(task.schedule(), Duration::from_secs(2));
+        assert!(task.reschedule().is_none());
+    }
+
+    #[test]
+    fn reschedule_backoff() {
+        let backoff = Backoff::new(Duration::from_secs(1), 2.0, Duration::from_secs(3)).unwrap();
+        let bond = TaskBond::Backoff(backoff, Duration::from_secs(1), JitterRng::new(0));
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::from_secs(0), bond, 42).unwrap();
+
+        let task = task.reschedule().unwrap();
+        assert_eq!(task.interval, Duration::from_secs(2));
+        assert_eq!(task.schedule(), Duration::from_secs(3));
+
+        let task = task.reschedule().unwrap().reschedule().unwrap();
+        assert_eq!(task.interval, Duration::from_secs(3));
+        assert_eq!(task.schedule(), Duration::from_secs(9));
+    }
+
+    #[test]
+    fn reschedule_run_jitter() {
+        let mut task = Task::new(TaskId(0), Duration::from_secs(10), Duration::from_secs(0), TaskBond::Perpetual, 42).unwrap();
+        task.run_jitter = Some(RunJitter::new(Duration::from_secs(5), JitterRng::new(0)));
+
+        for run in 1..10 {
+            let offset = task.run_jitter.as_ref().unwrap().offset;
+            assert!(offset < Duration::from_secs(5));
+            assert_eq!(task.schedule(), Duration::from_secs(10) * run + offset);
+            task = task.reschedule().unwrap();
+        }
+    }
+
+    #[test]
+    fn with_interval() {
+        let task = Task::new(TaskId(0), Duration::from_secs(30), Duration::from_secs(10), TaskBond::Perpetual, 1).unwrap();
+
+        let keep = task.clone().with_interval(Duration::from_secs(5), IntervalChange::KeepNextRun).unwrap();
+        assert_eq!(keep.schedule(), Duration::from_secs(40));
+        assert_eq!(keep.reschedule().unwrap().schedule(), Duration::from_secs(45));
+
+        let last = task.clone().with_interval(Duration::from_secs(5), IntervalChange::FromLastRun).unwrap();
+        assert_eq!(last.schedule(), Duration::from_secs(15));
+        assert_eq!(last.reschedule().unwrap().schedule(), Duration::from_secs(20));
+        assert_eq!(task.with_interval(Duration::MAX, IntervalChange::FromLastRun).err(), Some(SchedulerError::Overflow));
+    }
+
+    #[test]
+    fn overflow() {
+        let task = Task::new(TaskId(0), Duration::MAX / 2, Duration::new(0, 0), TaskBond::Perpetual, 42).unwrap();
+        let task = task.next().unwrap();
+        assert!(task.clone().next().is_none());
+        assert!(task.clone().reschedule().is_none());
+        assert!(task.clone().with_jitter(Jitter::Splay(Duration::from_secs(1)), JitterRng::new(1)).is_err());
+
+        let task = Task::new(TaskId(0), Duration::from_secs(1), Duration::new(0, 0), TaskBond::Until(Duration::MAX), 42).unwrap();
+        assert!(task.reschedule_from(Duration::MAX).is_none());
+    }
+
+    #[test]
+    fn reschedule_cron() {
+        let bond = TaskBond::Cron("*/5 * * * *".parse().unwrap(), UNIX_EPOCH + Duration::from_secs(60));
+        // epoch + 4 minutes is 00:05
+        let task = Task::new(TaskId(0), Duration::from_secs(4 * 60), Duration::from_secs(0), bond, 42).unwrap();
+
+        let task = task.reschedule().unwrap();
+        assert_eq!(task.due, Duration::from_secs(9 * 60));
+        assert_eq!(task.schedule(), Duration::from_secs(9 * 60));
+
+        let task = task.reschedule().unwrap();
+        assert_eq!(task.schedule(), Duration::from_secs(14 * 60));
     }
 }
@@ -0,0 +1,33 @@
+use task::*;
+
+pub type PointInTime = u64;
+
+// Storage of scheduled tasks ordered by time point they are due at
+pub trait TaskQueue<Token> where Token: Clone {
+    fn insert(&mut self, time_point: PointInTime, task: Task<Token>);
+    fn remove(&mut self, id: TaskId) -> Option<Task<Token>>;
+
+    fn get(&self, id: TaskId) -> Option<&Task<Token>>;
+    // must not be used to change when the task is due
+    fn get_mut(&mut self, id: TaskId) -> Option<&mut Task<Token>>;
+
+    // Earliest time point with tasks
+    fn first_time_point(&self) -> Option<PointInTime>;
+    // All time points with tasks that are before given time point in ascending order
+    fn time_points_before(&self, time_point: PointInTime) -> Vec<PointInTime>;
+    // Remove all tasks due at given time point in order of insertion
+    fn take(&mut self, time_point: PointInTime) -> Vec<Task<Token>>;
+
+    // All tasks in time point and insertion order
+    fn tasks(&self) -> Vec<&Task<Token>>;
+
+    fn retain<F>(&mut self, f: F) where F: FnMut(&Task<Token>) -> bool;
+    // Remove all tasks in time point and insertion order
+    fn drain(&mut self) -> Vec<Task<Token>>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
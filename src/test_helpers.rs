@@ -1,89 +1,102 @@
-use std::sync::{Mutex, Arc};
-use time::Duration;
+use scheduler::*;
 
-use time_source::*;
+pub use mock_time_source::*;
 
-pub struct MockTimeSource {
-    current_time: Duration
+// Strips Fired records down to tokens for easy comparison
+pub trait Tokens {
+    type Tokens;
+    fn tokens(self) -> Self::Tokens;
 }
 
-impl MockTimeSource {
-    pub fn new() -> MockTimeSource {
-        MockTimeSource {
-            current_time: Duration::seconds(0)
-        }
+impl<Token> Tokens for Vec<Fired<Token>> {
+    type Tokens = Vec<Token>;
+    fn tokens(self) -> Vec<Token> {
+        self.into_iter().map(|fired| fired.token).collect()
     }
 }
 
-impl FastForward for MockTimeSource {
-    fn fast_forward(&mut self, duration: Duration) {
-        self.current_time = self.current_time + duration;
+impl<T> Tokens for Option<T> where T: Tokens {
+    type Tokens = Option<T::Tokens>;
+    fn tokens(self) -> Option<T::Tokens> {
+        self.map(Tokens::tokens)
     }
 }
 
-impl TimeSource for MockTimeSource {
-    fn now(&self) -> Duration {
-        self.current_time
+impl<T, E> Tokens for Result<T, E> where T: Tokens, E: Tokens {
+    type Tokens = Result<T::Tokens, E::Tokens>;
+    fn tokens(self) -> Result<T::Tokens, E::Tokens> {
+        self.map(Tokens::tokens).map_err(Tokens::tokens)
     }
 }
 
-pub struct MockTimeSourceWait {
-    current_time: Duration,
-    abort: Arc<Mutex<bool>>
-}
-
-impl MockTimeSourceWait {
-    pub fn new() -> MockTimeSourceWait {
-        MockTimeSourceWait {
-            current_time: Duration::seconds(0),
-            abort: Arc::new(Mutex::new(false))
+impl<Token> Tokens for Schedule<Fired<Token>> {
+    type Tokens = Schedule<Token>;
+    fn tokens(self) -> Schedule<Token> {
+        match self {
+            Schedule::NextIn(duration) => Schedule::NextIn(duration),
+            Schedule::Overrun(fired) => Schedule::Overrun(fired.tokens()),
+            Schedule::Current(fired) => Schedule::Current(fired.tokens()),
+            Schedule::ClockJump(offset) => Schedule::ClockJump(offset)
         }
     }
 }
 
-impl FastForward for MockTimeSourceWait {
-    fn fast_forward(&mut self, duration: Duration) {
-        self.current_time = self.current_time + duration;
-    }
-}
-
-impl Wait for MockTimeSourceWait {
-    fn wait(&mut self, duration: Duration) {
-        self.current_time = self.current_time + duration;
+#[cfg(feature = "stream")]
+impl<Token> Tokens for FiredBatch<Token> {
+    type Tokens = Schedule<Token>;
+    fn tokens(self) -> Schedule<Token> {
+        match self {
+            FiredBatch::Current(fired) => Schedule::Current(fired.tokens()),
+            FiredBatch::Overrun(fired) => Schedule::Overrun(fired.tokens())
+        }
     }
 }
 
-impl TimeSource for MockTimeSourceWait {
-    fn now(&self) -> Duration {
-        self.current_time
+impl<Token> Tokens for WaitError<Fired<Token>> {
+    type Tokens = WaitError<Token>;
+    fn tokens(self) -> WaitError<Token> {
+        match self {
+            WaitError::Empty => WaitError::Empty,
+            WaitError::Overrun(fired) => WaitError::Overrun(fired.tokens()),
+            WaitError::ClockJump(offset) => WaitError::ClockJump(offset)
+        }
     }
 }
 
-pub struct MockTimeSourceAbortHandle {
-    abort: Arc<Mutex<bool>>
-}
-
-impl Abort for MockTimeSourceAbortHandle {
-    fn abort(&self) {
-        let mut abort = self.abort.lock().unwrap();
-        *abort = true;
+impl<Token> Tokens for WaitTimeoutError<Fired<Token>> {
+    type Tokens = WaitTimeoutError<Token>;
+    fn tokens(self) -> WaitTimeoutError<Token> {
+        match self {
+            WaitTimeoutError::Empty => WaitTimeoutError::Empty,
+            WaitTimeoutError::Timeout => WaitTimeoutError::Timeout,
+            WaitTimeoutError::Overrun(fired) => WaitTimeoutError::Overrun(fired.tokens()),
+            WaitTimeoutError::ClockJump(offset) => WaitTimeoutError::ClockJump(offset)
+        }
     }
 }
 
-impl AbortableWait for MockTimeSourceWait {
-    type AbortHandle = MockTimeSourceAbortHandle;
-
-    fn abort_handle(&self) -> Self::AbortHandle {
-        MockTimeSourceAbortHandle {
-            abort: self.abort.clone()
+impl<Token> Tokens for AbortableWaitError<Fired<Token>> {
+    type Tokens = AbortableWaitError<Token>;
+    fn tokens(self) -> AbortableWaitError<Token> {
+        match self {
+            AbortableWaitError::Empty => AbortableWaitError::Empty,
+            AbortableWaitError::Overrun(fired) => AbortableWaitError::Overrun(fired.tokens()),
+            AbortableWaitError::ClockJump(offset) => AbortableWaitError::ClockJump(offset),
+            AbortableWaitError::Aborted => AbortableWaitError::Aborted
         }
     }
+}
 
-    fn abortable_wait(&mut self, duration: Duration) -> Result<(), WaitAbortedError> {
-        if *self.abort.lock().unwrap() {
-            return Err(WaitAbortedError);
+impl<Token> Tokens for AbortableWaitTimeoutError<Fired<Token>> {
+    type Tokens = AbortableWaitTimeoutError<Token>;
+    fn tokens(self) -> AbortableWaitTimeoutError<Token> {
+        match self {
+            AbortableWaitTimeoutError::Empty => AbortableWaitTimeoutError::Empty,
+            AbortableWaitTimeoutError::Timeout => AbortableWaitTimeoutError::Timeout,
+            AbortableWaitTimeoutError::Overrun(fired) => AbortableWaitTimeoutError::Overrun(fired.tokens()),
+            AbortableWaitTimeoutError::ClockJump(offset) => AbortableWaitTimeoutError::ClockJump(offset),
+            AbortableWaitTimeoutError::Aborted => AbortableWaitTimeoutError::Aborted
         }
-        Ok(self.wait(duration))
     }
 }
 
@@ -1,10 +1,79 @@
 use std::fmt;
 use std::error::Error;
-use time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 pub trait TimeSource {
     // Duration since this TimeSource was crated
     fn now(&self) -> Duration;
+
+    // Wall clock step detected since last call (if any)
+    fn clock_jump(&mut self) -> Option<ClockJump> {
+        None
+    }
+}
+
+// Size and direction of wall clock step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockOffset {
+    Forward(Duration),
+    Backward(Duration)
+}
+
+impl ClockOffset {
+    pub fn between(from: SystemTime, to: SystemTime) -> ClockOffset {
+        match to.duration_since(from) {
+            Ok(offset) => ClockOffset::Forward(offset),
+            Err(err) => ClockOffset::Backward(err.duration())
+        }
+    }
+
+    // size of the step regardless of its direction
+    pub fn duration(&self) -> Duration {
+        match self {
+            &ClockOffset::Forward(offset) | &ClockOffset::Backward(offset) => offset
+        }
+    }
+
+    // given wall clock time moved by the offset
+    pub fn apply(&self, time: SystemTime) -> SystemTime {
+        match *self {
+            ClockOffset::Forward(offset) => time + offset,
+            ClockOffset::Backward(offset) => time - offset
+        }
+    }
+
+    // None if moved time cannot be represented
+    pub fn checked_apply(&self, time: SystemTime) -> Option<SystemTime> {
+        match *self {
+            ClockOffset::Forward(offset) => time.checked_add(offset),
+            ClockOffset::Backward(offset) => time.checked_sub(offset)
+        }
+    }
+}
+
+impl fmt::Display for ClockOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClockOffset::Forward(offset) => write!(f, "{}ms forward", offset.as_millis()),
+            ClockOffset::Backward(offset) => write!(f, "{}ms backward", offset.as_millis())
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockJump {
+    Reanchor(ClockOffset),
+    Report(ClockOffset)
+}
+
+pub trait WallClock: TimeSource {
+    // Wall clock time (UTC) at which now() was zero
+    fn epoch(&self) -> SystemTime;
+}
+
+pub trait InstantClock: TimeSource {
+    // time of the time source at given instant; None if it is before time source zero
+    fn time_at(&self, instant: Instant) -> Option<Duration>;
 }
 
 pub trait FastForward {
@@ -16,7 +85,10 @@ pub trait Wait {
 }
 
 pub trait Abort: Send {
+    // abort wait in progress or the next one if no wait is in progress; each abort ends one wait only
     fn abort(&self);
+    // cancel abort that did not end any wait yet
+    fn reset(&self);
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,7 +102,7 @@ impl Error for WaitAbortedError {
 
 impl fmt::Display for WaitAbortedError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.description())
+        write!(f, "wait operation aborted from another thread")
     }
 }
 
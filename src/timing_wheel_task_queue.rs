@@ -0,0 +1,418 @@
+use std::cell::Cell;
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+
+use task::*;
+use task_queue::*;
+
+const SLOT_BITS: usize = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+// enough levels to cover whole PointInTime range
+const LEVELS: usize = 64_usize.div_ceil(SLOT_BITS);
+// location of tasks due before the cursor
+const OVERDUE: usize = LEVELS;
+
+struct Entry<Token> where Token: Clone {
+    time_point: PointInTime,
+    // insertion order
+    sequence: u64,
+    task: Task<Token>,
+    level: usize,
+    slot: usize,
+    index: usize
+}
+
+// Hierarchical timing wheel with O(1) insert and remove; each level spans 64 times more time points
+// and its slots are cascaded down as the cursor moves
+pub struct TimingWheelTaskQueue<Token> where Token: Clone {
+    entries: HashMap<TaskId, Entry<Token>>,
+    // LEVELS levels of SLOTS slots followed by overdue tasks
+    slots: Vec<Vec<TaskId>>,
+    // earliest time point of each non-empty slot; None if it has to be found again after it was removed
+    minimums: Vec<Cell<Option<PointInTime>>>,
+    // bit set of non-empty slots for each level
+    occupied: [u64; LEVELS],
+    cursor: PointInTime,
+    next_sequence: u64
+}
+
+impl<Token> Default for TimingWheelTaskQueue<Token> where Token: Clone {
+    fn default() -> TimingWheelTaskQueue<Token> {
+        TimingWheelTaskQueue::new()
+    }
+}
+
+impl<Token> TimingWheelTaskQueue<Token> where Token: Clone {
+    pub fn new() -> TimingWheelTaskQueue<Token> {
+        TimingWheelTaskQueue {
+            entries: HashMap::new(),
+            slots: (0..LEVELS * SLOTS + 1).map(|_| Vec::new()).collect(),
+            minimums: (0..LEVELS * SLOTS + 1).map(|_| Cell::new(None)).collect(),
+            occupied: [0; LEVELS],
+            cursor: 0,
+            next_sequence: 0
+        }
+    }
+
+    fn location(&self, time_point: PointInTime) -> (usize, usize) {
+        if time_point < self.cursor {
+            return (OVERDUE, 0);
+        }
+
+        let differing = time_point ^ self.cursor;
+        let level = if differing == 0 {
+            0
+        } else {
+            (63 - differing.leading_zeros() as usize) / SLOT_BITS
+        };
+        (level, (time_point >> (level * SLOT_BITS)) as usize & (SLOTS - 1))
+    }
+
+    fn slot_index(level: usize, slot: usize) -> usize {
+        level * SLOTS + slot
+    }
+
+    fn place(&mut self, id: TaskId) {
+        let time_point = self.entries[&id].time_point;
+        let (level, slot) = self.location(time_point);
+
+        let minimum = &self.minimums[Self::slot_index(level, slot)];
+        let slot_tasks = &mut self.slots[Self::slot_index(level, slot)];
+        if slot_tasks.is_empty() {
+            minimum.set(Some(time_point));
+        } else if let Some(current) = minimum.get() {
+            minimum.set(Some(cmp::min(current, time_point)));
+        }
+        slot_tasks.push(id);
+        let index = slot_tasks.len() - 1;
+        if level != OVERDUE {
+            self.occupied[level] |= 1 << slot;
+        }
+
+        let entry = self.entries.get_mut(&id).unwrap();
+        entry.level = level;
+        entry.slot = slot;
+        entry.index = index;
+    }
+
+    fn unplace(&mut self, entry: &Entry<Token>) {
+        let (level, slot, index) = (entry.level, entry.slot, entry.index);
+        let minimum = &self.minimums[Self::slot_index(level, slot)];
+        if minimum.get() == Some(entry.time_point) {
+            minimum.set(None);
+        }
+        let slot_tasks = &mut self.slots[Self::slot_index(level, slot)];
+        slot_tasks.swap_remove(index);
+        if let Some(&moved) = slot_tasks.get(index) {
+            self.entries.get_mut(&moved).unwrap().index = index;
+        }
+        if slot_tasks.is_empty() && level != OVERDUE {
+            self.occupied[level] &= !(1 << slot);
+        }
+    }
+
+    // first non-empty level slot at or after the cursor
+    fn first_slot(&self) -> Option<(usize, usize)> {
+        for level in 0..LEVELS {
+            let cursor_slot = (self.cursor >> (level * SLOT_BITS)) as usize & (SLOTS - 1);
+            let occupied = self.occupied[level] & (!0u64 << cursor_slot);
+            if occupied != 0 {
+                return Some((level, occupied.trailing_zeros() as usize));
+            }
+        }
+        None
+    }
+
+    // earliest time point of non-empty slot
+    fn slot_minimum(&self, level: usize, slot: usize) -> PointInTime {
+        let minimum = &self.minimums[Self::slot_index(level, slot)];
+        minimum.get().unwrap_or_else(|| {
+            let time_point = self.slots[Self::slot_index(level, slot)].iter()
+                .map(|id| self.entries[id].time_point)
+                .min()
+                .expect("slot is not empty");
+            minimum.set(Some(time_point));
+            time_point
+        })
+    }
+
+    fn slot_time_points(&self, level: usize, slot: usize) -> Vec<PointInTime> {
+        let mut time_points: Vec<PointInTime> = self.slots[Self::slot_index(level, slot)].iter()
+            .map(|id| self.entries[id].time_point)
+            .collect();
+        time_points.sort();
+        time_points.dedup();
+        time_points
+    }
+
+    // move cursor forward to time point that no task is due before
+    fn advance(&mut self, cursor: PointInTime) {
+        self.cursor = cursor;
+
+        // tasks in slots containing new cursor now share more bits with it and belong to lower levels
+        for level in (1..LEVELS).rev() {
+            let slot = (cursor >> (level * SLOT_BITS)) as usize & (SLOTS - 1);
+            let ids = mem::take(&mut self.slots[Self::slot_index(level, slot)]);
+            self.occupied[level] &= !(1 << slot);
+            for id in ids {
+                self.place(id);
+            }
+        }
+    }
+}
+
+impl<Token> TaskQueue<Token> for TimingWheelTaskQueue<Token> where Token: Clone {
+    fn insert(&mut self, time_point: PointInTime, task: Task<Token>) {
+        let id = task.id;
+        if self.entries.contains_key(&id) {
+            self.remove(id);
+        }
+
+        self.entries.insert(id, Entry {
+            time_point,
+            sequence: self.next_sequence,
+            task,
+            level: 0,
+            slot: 0,
+            index: 0
+        });
+        self.next_sequence += 1;
+        self.place(id);
+    }
+
+    fn remove(&mut self, id: TaskId) -> Option<Task<Token>> {
+        let entry = self.entries.remove(&id)?;
+        self.unplace(&entry);
+        Some(entry.task)
+    }
+
+    fn get(&self, id: TaskId) -> Option<&Task<Token>> {
+        self.entries.get(&id).map(|entry| &entry.task)
+    }
+
+    fn get_mut(&mut self, id: TaskId) -> Option<&mut Task<Token>> {
+        self.entries.get_mut(&id).map(|entry| &mut entry.task)
+    }
+
+    fn first_time_point(&self) -> Option<PointInTime> {
+        // overdue tasks are all before the cursor
+        if !self.slots[Self::slot_index(OVERDUE, 0)].is_empty() {
+            return Some(self.slot_minimum(OVERDUE, 0));
+        }
+
+        self.first_slot().map(|(level, slot)| self.slot_minimum(level, slot))
+    }
+
+    fn time_points_before(&self, time_point: PointInTime) -> Vec<PointInTime> {
+        let mut time_points = Vec::new();
+        if !self.slots[Self::slot_index(OVERDUE, 0)].is_empty() && self.slot_minimum(OVERDUE, 0) < time_point {
+            time_points.extend(self.slot_time_points(OVERDUE, 0).into_iter().filter(|&overdue| overdue < time_point));
+        }
+
+        // levels and their slots after the cursor are in time order
+        'levels: for level in 0..LEVELS {
+            let cursor_slot = (self.cursor >> (level * SLOT_BITS)) as usize & (SLOTS - 1);
+            for slot in cursor_slot..SLOTS {
+                if self.occupied[level] & 1 << slot == 0 {
+                    continue;
+                }
+                if self.slot_minimum(level, slot) >= time_point {
+                    break 'levels;
+                }
+                for slot_time_point in self.slot_time_points(level, slot) {
+                    if slot_time_point >= time_point {
+                        break 'levels;
+                    }
+                    time_points.push(slot_time_point);
+                }
+            }
+        }
+        time_points
+    }
+
+    fn take(&mut self, time_point: PointInTime) -> Vec<Task<Token>> {
+        let first = self.first_time_point();
+        let (level, slot) = self.location(time_point);
+
+        let mut ids: Vec<TaskId> = self.slots[Self::slot_index(level, slot)].iter()
+            .filter(|id| self.entries[id].time_point == time_point)
+            .cloned()
+            .collect();
+        ids.sort_by_key(|id| self.entries[id].sequence);
+        let tasks = ids.into_iter().map(|id| self.remove(id).unwrap()).collect();
+
+        if first == Some(time_point) && time_point > self.cursor {
+            self.advance(time_point);
+        }
+        tasks
+    }
+
+    fn tasks(&self) -> Vec<&Task<Token>> {
+        let mut entries: Vec<&Entry<Token>> = self.entries.values().collect();
+        entries.sort_by_key(|entry| (entry.time_point, entry.sequence));
+        entries.into_iter().map(|entry| &entry.task).collect()
+    }
+
+    fn retain<F>(&mut self, mut f: F) where F: FnMut(&Task<Token>) -> bool {
+        let remove: Vec<TaskId> = self.entries.values()
+            .filter(|entry| !f(&entry.task))
+            .map(|entry| entry.task.id)
+            .collect();
+        for id in remove {
+            self.remove(id);
+        }
+    }
+
+    fn drain(&mut self) -> Vec<Task<Token>> {
+        for slot_tasks in self.slots.iter_mut() {
+            slot_tasks.clear();
+        }
+        self.occupied = [0; LEVELS];
+
+        let mut entries: Vec<Entry<Token>> = self.entries.drain().map(|(_, entry)| entry).collect();
+        entries.sort_by_key(|entry| (entry.time_point, entry.sequence));
+        entries.into_iter().map(|entry| entry.task).collect()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn task(id: u64) -> Task<u64> {
+        Task::new(TaskId(id), Duration::new(0, 0), Duration::new(0, 0), TaskBond::OneOff, id).unwrap()
+    }
+
+    fn ids(tasks: Vec<Task<u64>>) -> Vec<u64> {
+        tasks.into_iter().map(|task| task.token).collect()
+    }
+
+    #[test]
+    fn location() {
+        let mut queue: TimingWheelTaskQueue<u64> = TimingWheelTaskQueue::new();
+        assert_eq!(queue.location(0), (0, 0));
+        assert_eq!(queue.location(63), (0, 63));
+        assert_eq!(queue.location(64), (1, 1));
+        assert_eq!(queue.location(4095), (1, 63));
+        assert_eq!(queue.location(4096), (2, 1));
+        assert_eq!(queue.location(u64::MAX), (10, 15));
+
+        queue.cursor = 100;
+        assert_eq!(queue.location(99), (OVERDUE, 0));
+        assert_eq!(queue.location(100), (0, 36));
+        assert_eq!(queue.location(128), (1, 2));
+    }
+
+    #[test]
+    fn insert_take() {
+        let mut queue = TimingWheelTaskQueue::new();
+        assert_eq!(queue.first_time_point(), None);
+
+        queue.insert(3, task(0));
+        queue.insert(1, task(1));
+        queue.insert(3, task(2));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.first_time_point(), Some(1));
+        assert_eq!(queue.time_points_before(3), vec![1]);
+        assert_eq!(queue.time_points_before(4), vec![1, 3]);
+
+        assert_eq!(ids(queue.take(1)), vec![1]);
+        assert_eq!(ids(queue.take(3)), vec![0, 2]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.first_time_point(), None);
+    }
+
+    #[test]
+    fn cascade() {
+        let mut queue = TimingWheelTaskQueue::new();
+
+        let time_points = [5, 64, 70, 70, 4096, 4100, 300_000, 1 << 40, u64::MAX];
+        for (id, &time_point) in time_points.iter().enumerate().rev() {
+            queue.insert(time_point, task(id as u64));
+        }
+
+        let mut taken = Vec::new();
+        while let Some(time_point) = queue.first_time_point() {
+            let tasks = queue.take(time_point);
+            assert!(!tasks.is_empty());
+            taken.extend(tasks.iter().map(|_| time_point));
+        }
+        assert_eq!(taken, time_points.to_vec());
+    }
+
+    #[test]
+    fn overdue() {
+        let mut queue = TimingWheelTaskQueue::new();
+
+        queue.insert(200, task(0));
+        queue.take(200);
+        queue.insert(300, task(1));
+        queue.insert(150, task(2));
+        queue.insert(100, task(3));
+
+        assert_eq!(queue.first_time_point(), Some(100));
+        assert_eq!(queue.time_points_before(301), vec![100, 150, 300]);
+        assert_eq!(ids(queue.take(100)), vec![3]);
+        assert_eq!(ids(queue.take(150)), vec![2]);
+        assert_eq!(queue.first_time_point(), Some(300));
+    }
+
+    #[test]
+    fn remove_retain_drain() {
+        let mut queue = TimingWheelTaskQueue::new();
+
+        queue.insert(3, task(0));
+        queue.insert(1, task(1));
+        queue.insert(3, task(2));
+        queue.insert(5000, task(3));
+
+        assert_eq!(queue.remove(TaskId(1)).map(|task| task.token), Some(1));
+        assert_eq!(queue.remove(TaskId(1)).map(|task| task.token), None);
+        assert_eq!(queue.first_time_point(), Some(3));
+
+        queue.retain(|task| task.token != 2);
+        assert!(queue.get(TaskId(2)).is_none());
+        assert_eq!(queue.get(TaskId(0)).map(|task| task.token), Some(0));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.tasks().iter().map(|task| task.token).collect::<Vec<_>>(), vec![0, 3]);
+
+        assert_eq!(ids(queue.drain()), vec![0, 3]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.first_time_point(), None);
+    }
+
+    #[test]
+    fn crowded_slot() {
+        let mut queue = TimingWheelTaskQueue::new();
+
+        // all in a single level 3 slot
+        let tasks = 20_000;
+        for id in 0..tasks {
+            queue.insert((1 << 20) + tasks - id, task(id));
+        }
+        assert_eq!(queue.location(1 << 20), (3, 4));
+
+        // polling while waiting for the slot does not scan it
+        let start = Instant::now();
+        for _ in 0..tasks {
+            assert_eq!(queue.first_time_point(), Some((1 << 20) + 1));
+            assert!(queue.time_points_before(1 << 20).is_empty());
+        }
+        assert!(start.elapsed() < Duration::from_secs(2), "polling took {:?}", start.elapsed());
+
+        // earliest time point is found again when removed
+        queue.remove(TaskId(tasks - 1));
+        assert_eq!(queue.first_time_point(), Some((1 << 20) + 2));
+        assert_eq!(queue.time_points_before((1 << 20) + 4), vec![(1 << 20) + 2, (1 << 20) + 3]);
+        queue.insert(1 << 20, task(tasks));
+        assert_eq!(queue.first_time_point(), Some(1 << 20));
+        assert_eq!(ids(queue.take(1 << 20)), vec![tasks]);
+        assert_eq!(queue.first_time_point(), Some((1 << 20) + 2));
+    }
+}